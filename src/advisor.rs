@@ -0,0 +1,151 @@
+use crate::controller::SpotPrice;
+use crate::history::Record;
+use crate::table;
+use chrono::{DateTime, Utc};
+
+// the advice of an instance type in a zone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advice {
+    pub region: String,
+    pub zone: String,
+    pub instance_type: String,
+    pub price: f64, // the current price
+    pub mean: f64,
+    // the coefficient of variation of the prices, 0 if there is only one
+    pub volatility: f64,
+    // the current price divided by the pay-as-you-go price
+    pub discount: Option<f64>,
+    // the interruptions recorded locally since then
+    pub interruptions: usize,
+}
+
+impl Advice {
+    // the lower the better. the mean price is weighted by the volatility and the interruptions,
+    // as an unstable price or an interruption costs more than it looks
+    pub fn score(&self) -> f64 {
+        self.mean * (1.0 + self.volatility) * (1.0 + self.interruptions as f64)
+    }
+}
+
+// combine the prices of every region with the interruption history, and rank them by score
+pub fn advise(prices: &[(String, SpotPrice)], records: &[Record], since: DateTime<Utc>) -> Vec<Advice> {
+    let mut advices: Vec<Advice> = prices
+        .iter()
+        .filter(|(_, p)| !p.prices.is_empty())
+        .map(|(region, p)| {
+            let price = p.prices[p.prices.len() - 1];
+            let mean = p.prices.iter().sum::<f64>() / p.prices.len() as f64;
+            let variance = p.prices.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / p.prices.len() as f64;
+            let volatility = match mean > 0.0 {
+                true => variance.sqrt() / mean,
+                false => 0.0,
+            };
+            // the region or the zone of a record may be unknown, then it matches any one
+            let interruptions = records
+                .iter()
+                .filter(|r| r.time >= since)
+                .filter(|r| r.instance_type.as_deref() == Some(p.instance_type.as_str()))
+                .filter(|r| r.region.as_ref().map_or(true, |r| r == region))
+                .filter(|r| r.zone.as_ref().map_or(true, |z| *z == p.zone))
+                .count();
+            Advice {
+                region: region.clone(),
+                zone: p.zone.clone(),
+                instance_type: p.instance_type.clone(),
+                price,
+                mean,
+                volatility,
+                discount: p.origin.filter(|o| *o > 0.0).map(|o| price / o),
+                interruptions,
+            }
+        })
+        .collect();
+    advices.sort_by(|a, b| a.score().total_cmp(&b.score()));
+    advices
+}
+
+// a plain text table, example:
+// RANK  REGION       ZONE           TYPE          PRICE   MEAN    VOLATILITY  DISCOUNT  INTERRUPTIONS
+// 1     cn-hangzhou  cn-hangzhou-i  ecs.g7.large  0.1200  0.1300  5.2%        20.0%     0
+pub fn render(advices: &[Advice]) -> String {
+    let header = ["RANK", "REGION", "ZONE", "TYPE", "PRICE", "MEAN", "VOLATILITY", "DISCOUNT", "INTERRUPTIONS"];
+    let mut rows = vec![header.iter().map(|h| h.to_string()).collect::<Vec<String>>()];
+    for (i, a) in advices.iter().enumerate() {
+        rows.push(vec![
+            (i + 1).to_string(),
+            a.region.clone(),
+            a.zone.clone(),
+            a.instance_type.clone(),
+            format!("{:.4}", a.price),
+            format!("{:.4}", a.mean),
+            format!("{:.1}%", a.volatility * 100.0),
+            a.discount.map(|d| format!("{:.1}%", d * 100.0)).unwrap_or("-".to_string()),
+            a.interruptions.to_string(),
+        ]);
+    }
+    table::render(&rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alert::Code;
+    use chrono::TimeDelta;
+
+    fn price(zone: &str, instance_type: &str, prices: Vec<f64>) -> (String, SpotPrice) {
+        let p = SpotPrice {
+            zone: zone.to_string(),
+            instance_type: instance_type.to_string(),
+            prices,
+            origin: Some(1.0),
+        };
+        ("cn-hangzhou".to_string(), p)
+    }
+
+    fn record(instance_type: &str, days: i64) -> Record {
+        Record {
+            time: Utc::now() - TimeDelta::days(days),
+            code: Code::AliCloudInterrupt,
+            provider: None,
+            instance_id: "i-1".to_string(),
+            instance_type: Some(instance_type.to_string()),
+            region: None,
+            zone: None,
+            boot: None,
+            termination: None,
+            hooks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_advise() {
+        let prices = vec![
+            price("cn-hangzhou-i", "ecs.g7.large", vec![0.2, 0.2]),
+            price("cn-hangzhou-j", "ecs.c7.large", vec![0.1, 0.3]),
+            price("cn-hangzhou-k", "ecs.r7.large", vec![0.14]),
+            price("cn-hangzhou-k", "ecs.empty", vec![]),
+        ];
+        // the one recorded 40 days ago is out of range
+        let records = vec![record("ecs.r7.large", 1), record("ecs.g7.large", 40)];
+        let advices = advise(&prices, &records, Utc::now() - TimeDelta::days(30));
+
+        let ranked: Vec<&str> = advices.iter().map(|a| a.instance_type.as_str()).collect();
+        // 0.2 * 1 * 1, 0.14 * 1 * 2, 0.2 * 1.5 * 1
+        assert_eq!(ranked, vec!["ecs.g7.large", "ecs.r7.large", "ecs.c7.large"]);
+        assert_eq!(advices[2].price, 0.3);
+        assert!((advices[2].volatility - 0.5).abs() < 1e-9);
+        assert_eq!(advices[2].discount, Some(0.3));
+        assert_eq!(advices[1].interruptions, 1);
+        assert_eq!(advices[0].interruptions, 0);
+    }
+
+    #[test]
+    fn test_render() {
+        let advices = advise(&[price("cn-hangzhou-i", "ecs.g7.large", vec![0.12])], &[], Utc::now());
+        assert_eq!(
+            render(&advices),
+            "RANK  REGION       ZONE           TYPE          PRICE   MEAN    VOLATILITY  DISCOUNT  INTERRUPTIONS\n\
+             1     cn-hangzhou  cn-hangzhou-i  ecs.g7.large  0.1200  0.1200  0.0%        12.0%     0\n"
+        );
+    }
+}
@@ -1,7 +1,8 @@
 mod feishu;
 
-use chrono::{FixedOffset, Local};
+use chrono::{DateTime, FixedOffset, Utc};
 use log::{error, info};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::{fmt, error::Error};
 use sysinfo::System;
@@ -9,12 +10,35 @@ use sysinfo::System;
 // all the events must transfer Msg instance
 // - code is the type of event
 // - target is the source of event
+// - termination is the time when the spot instance will be released, if any
+// - notes are extra lines shown in the alert, e.g. the results of hooks
+// - labels describe the target, e.g. the instance id and the region
 #[derive(Debug)]
 pub struct Msg {
     code: Code,
     target: Target,
     hostname: String,
     datetime: String,
+    termination: Option<Termination>,
+    notes: Vec<String>,
+    labels: Labels,
+}
+
+// the labels in order, example: [("instance_id", "i-bp1..."), ("region", "cn-hangzhou")]
+pub type Labels = Vec<(String, String)>;
+
+// the termination time and the remaining seconds when the message is created
+#[derive(Debug, Clone, PartialEq)]
+pub struct Termination {
+    pub at: DateTime<Utc>,
+    pub remaining: i64,
+}
+
+impl fmt::Display for Termination {
+    // example: 2024-09-11 16:43:21（剩余120秒）
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}（剩余{}秒）", format_time(&self.at), self.remaining)
+    }
 }
 
 impl Msg {
@@ -24,15 +48,76 @@ impl Msg {
             target,
             hostname: hostname(),
             datetime: now(),
+            termination: None,
+            notes: vec![],
+            labels: vec![],
         }
     }
+
+    // attach the termination time of spot instance
+    pub fn with_termination(mut self, at: DateTime<Utc>) -> Self {
+        let remaining = (at - Utc::now()).num_seconds().max(0);
+        self.termination = Some(Termination { at, remaining });
+        self
+    }
+
+    // append an extra line
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    // attach the labels of the target, the existing ones are kept
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.labels.extend(labels);
+        self
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    pub fn datetime(&self) -> &str {
+        &self.datetime
+    }
+
+    pub fn termination(&self) -> Option<&Termination> {
+        self.termination.as_ref()
+    }
+
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
 }
 
 // china standard time（UTC +8）
 // example: 2024-09-11 16:43:21
 fn now() -> String {
+    format_time(&Utc::now())
+}
+
+// format a time in china standard time（UTC +8）
+pub fn format_time(t: &DateTime<Utc>) -> String {
+    let offset = FixedOffset::east_opt(8 * 60 * 60).unwrap();
+    t.with_timezone(&offset).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+// the hour and the minute only, example: 16:43
+pub fn format_clock(t: &DateTime<Utc>) -> String {
     let offset = FixedOffset::east_opt(8 * 60 * 60).unwrap();
-    Local::now().with_timezone(&offset).format("%Y-%m-%d %H:%M:%S").to_string()
+    t.with_timezone(&offset).format("%H:%M").to_string()
 }
 
 // system's host name
@@ -40,16 +125,48 @@ pub fn hostname() -> String {
     System::host_name().unwrap_or_else(|| "".to_string())
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Code {
     // the spot instance of AliCloud will terminate.
     AliCloudInterrupt,
     // the spot instance of TencentCloud will terminate.
     TencentCloudInterrupt,
+    // the spot instance of HuaweiCloud will terminate.
+    HuaweiCloudInterrupt,
+    // the preemptible instance of Volcengine will terminate.
+    VolcengineInterrupt,
+    // the spot instance of AWS EC2 will be interrupted (terminate, stop or hibernate).
+    AwsInterrupt,
+    // AWS EC2 recommends rebalancing because the spot instance is at elevated risk of interruption.
+    AwsRebalance,
+    // the preemptible or spot vm of GCP is preempted.
+    GcpPreempted,
+    // a host maintenance event of GCP is scheduled, the vm will be migrated or terminated.
+    GcpMaintenance,
+    // the spot vm of Azure will be evicted (Preempt or Terminate).
+    AzureInterrupt,
+    // the vm of Azure will be rebooted or redeployed.
+    AzureMaintenance,
+    // the report of the pre-termination hooks of spot instance.
+    SpotHookReport,
+    // the termination notice of spot instance is withdrawn.
+    SpotCancelled,
+    // the spot instance can't be monitored properly, e.g. the metadata service keeps failing.
+    MonitorDegraded,
+    // the spot instance can be monitored again after degraded.
+    MonitorRecovered,
+    // a replacement of the released spot instance is launched.
+    SpotReplaced,
+    // the replacement of the released spot instance can't be launched.
+    SpotReplaceFailed,
     // the server is offline because of network, power outage, etc.
     // detect with another server
     Offline,
     Online,
+    // a client which isn't listed in `[[keepalive.server.clients]]` connects for the first time.
+    UnknownClient,
+    // the client is stopped deliberately, e.g. systemctl stop or a reboot.
+    PlannedShutdown,
 }
 
 impl fmt::Display for Code {
@@ -57,8 +174,40 @@ impl fmt::Display for Code {
         match self {
             Code::AliCloudInterrupt => write!(f, "阿里云服务器释放通知"),
             Code::TencentCloudInterrupt => write!(f, "腾讯云服务器释放通知"),
+            Code::HuaweiCloudInterrupt => write!(f, "华为云服务器释放通知"),
+            Code::VolcengineInterrupt => write!(f, "火山引擎服务器释放通知"),
+            Code::AwsInterrupt => write!(f, "AWS服务器中断通知"),
+            Code::AwsRebalance => write!(f, "AWS服务器再平衡建议"),
+            Code::GcpPreempted => write!(f, "GCP服务器抢占通知"),
+            Code::GcpMaintenance => write!(f, "GCP服务器维护通知"),
+            Code::AzureInterrupt => write!(f, "Azure服务器驱逐通知"),
+            Code::AzureMaintenance => write!(f, "Azure服务器维护通知"),
+            Code::SpotHookReport => write!(f, "竞价实例释放前处理报告"),
+            Code::SpotCancelled => write!(f, "竞价实例释放取消通知"),
+            Code::MonitorDegraded => write!(f, "竞价实例监控异常通知"),
+            Code::MonitorRecovered => write!(f, "竞价实例监控恢复通知"),
+            Code::SpotReplaced => write!(f, "竞价实例替换通知"),
+            Code::SpotReplaceFailed => write!(f, "竞价实例替换失败通知"),
             Code::Offline => write!(f, "服务器离线通知"),
             Code::Online => write!(f, "服务器上线通知"),
+            Code::UnknownClient => write!(f, "未知客户端接入通知"),
+            Code::PlannedShutdown => write!(f, "服务器计划停机通知"),
+        }
+    }
+}
+
+impl Code {
+    // the cloud sending the event, named as `config::Provider`. None if it isn't from a cloud
+    pub fn provider(&self) -> Option<&'static str> {
+        match self {
+            Code::AliCloudInterrupt => Some("AliCloud"),
+            Code::TencentCloudInterrupt => Some("TencentCloud"),
+            Code::HuaweiCloudInterrupt => Some("HuaweiCloud"),
+            Code::VolcengineInterrupt => Some("Volcengine"),
+            Code::AwsInterrupt | Code::AwsRebalance => Some("AwsEc2"),
+            Code::GcpPreempted | Code::GcpMaintenance => Some("Gcp"),
+            Code::AzureInterrupt | Code::AzureMaintenance => Some("Azure"),
+            _ => None,
         }
     }
 }
@@ -118,7 +267,7 @@ impl Alert {
 #[cfg(test)]
 mod test {
     use super::*;
-    use chrono::NaiveDateTime;
+    use chrono::{NaiveDateTime, TimeZone};
     use std::collections::HashMap;
     use std::error::Error;
 
@@ -129,6 +278,35 @@ mod test {
         assert!(NaiveDateTime::parse_from_str(&datetime, fmt).is_ok());
     }
 
+    #[test]
+    fn test_termination() {
+        let at = Utc.with_ymd_and_hms(2024, 9, 11, 8, 43, 21).unwrap();
+        assert_eq!("2024-09-11 16:43:21", format_time(&at));
+        assert_eq!("16:43", format_clock(&at));
+        // the time has passed
+        let msg = Msg::new(Code::AliCloudInterrupt, Target::Myself("hi".to_string())).with_termination(at);
+        assert_eq!(msg.termination().unwrap().to_string(), "2024-09-11 16:43:21（剩余0秒）");
+
+        let msg = Msg::new(Code::AliCloudInterrupt, Target::Myself("hi".to_string()))
+            .with_termination(Utc::now() + chrono::TimeDelta::seconds(120));
+        let remaining = msg.termination().unwrap().remaining;
+        assert!((119..=120).contains(&remaining));
+        assert!(Msg::new(Code::Online, Target::Myself("hi".to_string())).termination().is_none());
+    }
+
+    #[test]
+    fn test_labels() {
+        let msg = Msg::new(Code::Online, Target::Myself("hi".to_string()));
+        assert!(msg.labels().is_empty());
+        let msg = msg
+            .with_labels(vec![("instance_id".to_string(), "i-1".to_string())])
+            .with_labels(vec![("region".to_string(), "cn-hangzhou".to_string())]);
+        assert_eq!(msg.labels(), &vec![
+            ("instance_id".to_string(), "i-1".to_string()),
+            ("region".to_string(), "cn-hangzhou".to_string()),
+        ]);
+    }
+
     #[test]
     fn test_hostname() {
         assert!(!hostname().is_empty());
@@ -138,8 +316,33 @@ mod test {
     fn test_code() {
         assert_eq!("阿里云服务器释放通知", Code::AliCloudInterrupt.to_string());
         assert_eq!("腾讯云服务器释放通知", Code::TencentCloudInterrupt.to_string());
+        assert_eq!("华为云服务器释放通知", Code::HuaweiCloudInterrupt.to_string());
+        assert_eq!("火山引擎服务器释放通知", Code::VolcengineInterrupt.to_string());
+        assert_eq!("AWS服务器中断通知", Code::AwsInterrupt.to_string());
+        assert_eq!("AWS服务器再平衡建议", Code::AwsRebalance.to_string());
+        assert_eq!("GCP服务器抢占通知", Code::GcpPreempted.to_string());
+        assert_eq!("GCP服务器维护通知", Code::GcpMaintenance.to_string());
+        assert_eq!("Azure服务器驱逐通知", Code::AzureInterrupt.to_string());
+        assert_eq!("Azure服务器维护通知", Code::AzureMaintenance.to_string());
+        assert_eq!("竞价实例释放前处理报告", Code::SpotHookReport.to_string());
+        assert_eq!("竞价实例释放取消通知", Code::SpotCancelled.to_string());
+        assert_eq!("竞价实例监控异常通知", Code::MonitorDegraded.to_string());
+        assert_eq!("竞价实例监控恢复通知", Code::MonitorRecovered.to_string());
+        assert_eq!("竞价实例替换通知", Code::SpotReplaced.to_string());
+        assert_eq!("竞价实例替换失败通知", Code::SpotReplaceFailed.to_string());
         assert_eq!("服务器离线通知", Code::Offline.to_string());
         assert_eq!("服务器上线通知", Code::Online.to_string());
+        assert_eq!("未知客户端接入通知", Code::UnknownClient.to_string());
+        assert_eq!("服务器计划停机通知", Code::PlannedShutdown.to_string());
+    }
+
+    #[test]
+    fn test_code_provider() {
+        assert_eq!(Some("AliCloud"), Code::AliCloudInterrupt.provider());
+        assert_eq!(Some("AwsEc2"), Code::AwsRebalance.provider());
+        assert_eq!(Some("Azure"), Code::AzureMaintenance.provider());
+        assert_eq!(None, Code::SpotHookReport.provider());
+        assert_eq!(None, Code::Offline.provider());
     }
 
     #[test]
@@ -16,12 +16,47 @@ impl Notice for Feishu {
     阿里云服务器释放通知
     目标实例：myself(Hi)
     主机名称：JQS-MacbookPro.local
+    instance_id：i-bp1...
+    region：cn-hangzhou
+    释放时间：2024-09-12 15:56:54（剩余300秒）
     --------
     报警时间：2024-09-12 15:51:54
      */
     fn send(&self, msg: &Msg) -> Result<(), Box<dyn Error>> {
         let timestamp = Utc::now().timestamp();
         let sign = self.sign(timestamp)?;
+        let mut content = vec![
+            json!([{
+                "tag": "text",
+                "text": format!("目标实例：{}", msg.target),
+            }]),
+            json!([{
+                "tag": "text",
+                "text": format!("主机名称：{}", msg.hostname),
+            }]),
+        ];
+        for (k, v) in &msg.labels {
+            content.push(json!([{
+                "tag": "text",
+                "text": format!("{k}：{v}"),
+            }]));
+        }
+        if let Some(t) = &msg.termination {
+            content.push(json!([{
+                "tag": "text",
+                "text": format!("释放时间：{}", t),
+            }]));
+        }
+        for note in &msg.notes {
+            content.push(json!([{
+                "tag": "text",
+                "text": note,
+            }]));
+        }
+        content.push(json!([{
+            "tag": "text",
+            "text": format!("--------\n报警时间：{}", msg.datetime),
+        }]));
         let data = json!({
             "timestamp": timestamp,
             "sign": sign,
@@ -30,20 +65,7 @@ impl Notice for Feishu {
                 "post": {
                     "zh_cn": {
                         "title": msg.code.to_string(),
-                        "content": [
-                            [{
-                                "tag": "text",
-                                "text": format!("目标实例：{}", msg.target),
-                            }],
-                            [{
-                                "tag": "text",
-                                "text": format!("主机名称：{}", msg.hostname),
-                            }],
-                            [{
-                                "tag": "text",
-                                "text": format!("--------\n报警时间：{}", msg.datetime),
-                            }]
-                        ]
+                        "content": content
                     }
                 }
             }
@@ -106,6 +128,42 @@ mod test {
         mock.assert();
     }
 
+    #[test]
+    fn test_send_termination() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/feishu")
+            .match_body(mockito::Matcher::Regex("释放时间：2024-09-12 15:56:54（剩余0秒）".to_string()))
+            .with_body("ok")
+            .create();
+
+        let fe = Feishu {
+            webhook: format!("{}/feishu", server.url()),
+            secret: "plaintext".to_string(),
+        };
+        let at = chrono::DateTime::parse_from_rfc3339("2024-09-12T07:56:54Z").unwrap().to_utc();
+        let msg = Msg::new(Code::AliCloudInterrupt, Target::Myself("superman".to_string())).with_termination(at);
+        fe.send(&msg).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_labels() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/feishu")
+            .match_body(mockito::Matcher::Regex("region：cn-hangzhou".to_string()))
+            .with_body("ok")
+            .create();
+
+        let fe = Feishu {
+            webhook: format!("{}/feishu", server.url()),
+            secret: "plaintext".to_string(),
+        };
+        let msg = Msg::new(Code::AliCloudInterrupt, Target::Myself("superman".to_string()))
+            .with_labels(vec![("region".to_string(), "cn-hangzhou".to_string())]);
+        fe.send(&msg).unwrap();
+        mock.assert();
+    }
+
     #[test]
     #[should_panic(expected = "request error")]
     fn test_send_err() {
@@ -0,0 +1,725 @@
+use crate::alert::Target::Another;
+use crate::alert::{Alert, Code, Msg};
+use crate::config;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+mod alicloud;
+mod tencentcloud;
+
+pub use alicloud::AliCloudApi;
+pub use tencentcloud::TencentCloudApi;
+
+// the tags of an instance, sorted by key
+pub type Tags = BTreeMap<String, String>;
+
+// the api of a cloud provider, which describes the instances from the outside
+pub trait CloudApi: Send + Sync {
+    // the alert code used once an instance is interrupted
+    fn code(&self) -> Code;
+
+    // where the instances live, e.g. the region
+    fn region(&self) -> String;
+
+    // every instance found in `ids`, the missing ones are regarded as released
+    fn describe(&self, ids: &[String]) -> Result<HashMap<String, Instance>, Box<dyn Error>>;
+
+    // launch an instance from the launch template with the tags, and return its id
+    fn run_instance(&self, template: &str, tags: &Tags) -> Result<String, Box<dyn Error>>;
+
+    // terminate an instance, i.e. a replacement which isn't needed anymore
+    fn terminate_instance(&self, id: &str) -> Result<(), Box<dyn Error>>;
+
+    // the spot prices of the instance types in every zone since the time, if the history is available
+    fn spot_prices(&self, _instance_types: &[String], _since: DateTime<Utc>) -> Result<Vec<SpotPrice>, Box<dyn Error>> {
+        Err(Box::from("spot price is not supported"))
+    }
+}
+
+// the spot price of an instance type in a zone, per hour
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotPrice {
+    pub zone: String,
+    pub instance_type: String,
+    // in chronological order, the last one is the current price
+    pub prices: Vec<f64>,
+    // the pay-as-you-go price
+    pub origin: Option<f64>,
+}
+
+// an instance seen by the cloud api
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Instance {
+    pub state: State,
+    // the public ip if any, otherwise the private one
+    pub ip: Option<String>,
+    pub tags: Tags,
+}
+
+// the state of an instance seen by the cloud api
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum State {
+    #[default]
+    Running,
+    Stopped,
+    // the spot instance is being reclaimed
+    Recycling,
+    // the instance doesn't exist anymore
+    Released,
+    Other(String),
+}
+
+impl State {
+    // the instance is interrupted, or is going to be
+    fn is_down(&self) -> bool {
+        matches!(self, State::Stopped | State::Recycling | State::Released)
+    }
+
+    // the instance is reclaimed by the cloud, rather than stopped by someone
+    fn is_gone(&self) -> bool {
+        matches!(self, State::Recycling | State::Released)
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Running => write!(f, "运行中"),
+            State::Stopped => write!(f, "已停止"),
+            State::Recycling => write!(f, "回收中"),
+            State::Released => write!(f, "已释放"),
+            State::Other(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+// create the api client of an account, None if the provider has no api support
+pub fn api(account: &config::Account) -> Option<Box<dyn CloudApi>> {
+    match account.provider {
+        config::Provider::AliCloud => Some(Box::new(AliCloudApi::new(account))),
+        config::Provider::TencentCloud => Some(Box::new(TencentCloudApi::new(account))),
+        p => {
+            warn!("controller - {:?} is not supported", p);
+            None
+        }
+    }
+}
+
+// create the recovery of an account, None if no launch template is configured
+pub fn recovery(account: &config::Account) -> Option<Recovery> {
+    let template = account.launch_template.as_ref()?;
+    Some(Recovery::new(api(account)?, template, Duration::from_secs(account.launch_timeout)))
+}
+
+// the replacement launched by the recovery
+#[derive(Debug, PartialEq)]
+pub struct Replacement {
+    pub id: String,
+    pub instance: Instance,
+}
+
+// the error of a recovery which is given up
+const WITHDRAWN: &str = "the interruption is withdrawn";
+
+// Recovery launches a replacement of the released instance from a launch template,
+// and waits for it to run.
+pub struct Recovery {
+    api: Box<dyn CloudApi>,
+    template: String,
+    timeout: Duration,
+    poll: Duration,
+}
+
+impl Recovery {
+    pub fn new(api: Box<dyn CloudApi>, template: &str, timeout: Duration) -> Recovery {
+        Recovery {
+            api,
+            template: template.to_string(),
+            timeout,
+            poll: Duration::from_secs(5),
+        }
+    }
+
+    // the interval of checking the replacement, usually for testing
+    pub fn with_poll(mut self, poll: Duration) -> Recovery {
+        self.poll = poll;
+        self
+    }
+
+    // replace the instance `id` with the same tags. if `tags` is None, they are described at first.
+    // the replacement is returned even if it isn't running before timeout
+    pub fn recover(&self, id: &str, tags: Option<&Tags>) -> Result<Replacement, Box<dyn Error>> {
+        self.recover_unless(id, tags, &AtomicBool::new(false))
+    }
+
+    // give up once `withdrawn` is set, the replacement is terminated if it has been launched
+    fn recover_unless(&self, id: &str, tags: Option<&Tags>, withdrawn: &AtomicBool) -> Result<Replacement, Box<dyn Error>> {
+        let tags = match tags {
+            Some(t) => t.clone(),
+            None => self
+                .api
+                .describe(&[id.to_string()])?
+                .remove(id)
+                .map(|i| i.tags)
+                .unwrap_or_default(),
+        };
+        if withdrawn.load(Ordering::Relaxed) {
+            return Err(Box::from(WITHDRAWN));
+        }
+        let new_id = self.api.run_instance(&self.template, &tags)?;
+        info!("recovery - launch {new_id} to replace {id}");
+
+        let start = Instant::now();
+        loop {
+            if withdrawn.load(Ordering::Relaxed) {
+                self.terminate(&new_id);
+                return Err(Box::from(WITHDRAWN));
+            }
+            let instance = match self.api.describe(std::slice::from_ref(&new_id)) {
+                Ok(mut found) => found.remove(&new_id),
+                Err(err) => {
+                    error!("recovery - describe {new_id} error: {err}");
+                    None
+                }
+            };
+            match instance {
+                Some(i) if i.state == State::Running => return Ok(Replacement { id: new_id, instance: i }),
+                i if start.elapsed() >= self.timeout => {
+                    warn!("recovery - {new_id} is not running after {}s", self.timeout.as_secs());
+                    let instance = i.unwrap_or(Instance { state: State::Other("未知".to_string()), ..Default::default() });
+                    return Ok(Replacement { id: new_id, instance });
+                }
+                _ => thread::sleep(self.poll),
+            }
+        }
+    }
+
+    // recover the instance, and report the result
+    pub fn report(&self, alert: &Alert, id: &str, tags: Option<&Tags>) -> Option<Replacement> {
+        self.report_unless(alert, id, tags, &AtomicBool::new(false))
+    }
+
+    // recover the instance in a child thread, so that the caller isn't blocked until it runs
+    pub fn launch(self: &Arc<Self>, alert: Arc<Alert>, id: &str, tags: Option<Tags>) -> Launch {
+        let withdrawn = Arc::new(AtomicBool::new(false));
+        let recovery = Arc::clone(self);
+        let flag = Arc::clone(&withdrawn);
+        let id = id.to_string();
+        let handle = thread::spawn(move || recovery.report_unless(&alert, &id, tags.as_ref(), &flag));
+        Launch {
+            recovery: Arc::clone(self),
+            withdrawn,
+            handle,
+        }
+    }
+
+    fn report_unless(&self, alert: &Alert, id: &str, tags: Option<&Tags>, withdrawn: &AtomicBool) -> Option<Replacement> {
+        match self.recover_unless(id, tags, withdrawn) {
+            // it is terminated by the withdrawal
+            Ok(r) if withdrawn.load(Ordering::Relaxed) => Some(r),
+            Err(_) if withdrawn.load(Ordering::Relaxed) => {
+                info!("recovery - the replacement of {id} is given up");
+                None
+            }
+            Ok(r) => {
+                let msg = Msg::new(Code::SpotReplaced, Another(id.to_string()))
+                    .with_note(&format!("原实例：{id}"))
+                    .with_note(&format!("新实例：{}", r.id))
+                    .with_note(&format!("实例状态：{}", r.instance.state))
+                    .with_note(&format!("IP 地址：{}", r.instance.ip.as_deref().unwrap_or("-")));
+                alert.send(&msg);
+                Some(r)
+            }
+            Err(err) => {
+                error!("recovery - replace {id} error: {err}");
+                let msg = Msg::new(Code::SpotReplaceFailed, Another(id.to_string()))
+                    .with_note(&format!("原实例：{id}"))
+                    .with_note(&format!("失败原因：{err}"));
+                alert.send(&msg);
+                None
+            }
+        }
+    }
+
+    fn terminate(&self, id: &str) {
+        match self.api.terminate_instance(id) {
+            Ok(_) => info!("recovery - terminate {id} since the interruption is withdrawn"),
+            Err(err) => error!("recovery - terminate {id} error: {err}"),
+        }
+    }
+}
+
+// a recovery running in the background, see `Recovery::launch`
+pub struct Launch {
+    recovery: Arc<Recovery>,
+    withdrawn: Arc<AtomicBool>,
+    handle: JoinHandle<Option<Replacement>>,
+}
+
+impl Launch {
+    // the replacement once the launch is finished, None if it failed. the launch itself if it is running
+    pub fn try_finish(self) -> Result<Option<Replacement>, Launch> {
+        match self.handle.is_finished() {
+            true => Ok(self.handle.join().ok().flatten()),
+            false => Err(self),
+        }
+    }
+
+    // the interruption is withdrawn, so the replacement is terminated in the background once it is launched.
+    // join the handle to wait for that
+    pub fn withdraw(self) -> JoinHandle<()> {
+        self.withdrawn.store(true, Ordering::Relaxed);
+        let recovery = self.recovery;
+        let handle = self.handle;
+        thread::spawn(move || {
+            if let Ok(Some(r)) = handle.join() {
+                recovery.terminate(&r.id);
+            }
+        })
+    }
+}
+
+// the instances watched through an api
+struct Target {
+    api: Box<dyn CloudApi>,
+    ids: Vec<String>,
+    recovery: Option<Arc<Recovery>>,
+    launches: HashMap<String, Launch>, // the replacements in progress, by the replaced id
+}
+
+// Controller polls the cloud apis for a list of instances at regular intervals,
+// and sends an alert once an instance is stopped, reclaimed or released.
+// if a recovery is set, a replacement of the reclaimed instance is launched in the background,
+// and it is watched instead once the instance is released. it is terminated if the instance runs again.
+pub struct Controller {
+    interval: u64,
+    alert: Arc<Alert>,
+    targets: Vec<Target>,
+}
+
+impl Controller {
+    pub fn new(interval: u64, alert: Arc<Alert>) -> Controller {
+        Controller {
+            interval,
+            alert,
+            targets: vec![],
+        }
+    }
+
+    // watch the instances through the api
+    pub fn watch(mut self, api: Box<dyn CloudApi>, instances: Vec<String>, recovery: Option<Recovery>) -> Controller {
+        self.targets.push(Target {
+            api,
+            ids: instances,
+            recovery: recovery.map(Arc::new),
+            launches: HashMap::new(),
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    pub fn patrol(mut self) {
+        let mut seen = HashMap::new();
+        // super loop
+        loop {
+            self.tick(&mut seen);
+            thread::sleep(Duration::from_secs(self.interval));
+        }
+    }
+
+    // a single patrol, `seen` keeps the last instance of every id
+    fn tick(&mut self, seen: &mut HashMap<String, Instance>) {
+        for target in self.targets.iter_mut() {
+            let mut current = match target.api.describe(&target.ids) {
+                Ok(c) => c,
+                Err(err) => {
+                    error!("controller - describe instances in {} error: {}", target.api.region(), err);
+                    continue;
+                }
+            };
+            let mut replaced = vec![];
+            for id in target.ids.iter() {
+                let last = seen.get(id).cloned();
+                // the tags of a released instance are remembered
+                let instance = current.remove(id).unwrap_or_else(|| Instance {
+                    state: State::Released,
+                    ip: None,
+                    tags: last.as_ref().map(|i| i.tags.clone()).unwrap_or_default(),
+                });
+                seen.insert(id.clone(), instance.clone());
+                if instance.state == State::Released {
+                    if let Some(launch) = target.launches.remove(id) {
+                        match launch.try_finish() {
+                            Ok(Some(r)) => replaced.push((id.clone(), r.id)),
+                            Ok(None) => {}
+                            Err(launch) => {
+                                target.launches.insert(id.clone(), launch);
+                            }
+                        }
+                    }
+                }
+                let last = last.map(|i| i.state);
+                if last.as_ref() == Some(&instance.state) {
+                    continue;
+                }
+                info!("controller - the instance {id} is {:?}", instance.state);
+                // alert once it becomes down, including the first check
+                if instance.state.is_down() {
+                    let msg = Msg::new(target.api.code(), Another(id.clone()))
+                        .with_note(&format!("实例状态：{}", instance.state))
+                        .with_note(&format!("所在地域：{}", target.api.region()));
+                    self.alert.send(&msg);
+                }
+                // replace it only once, e.g. Recycling -> Released. an instance gone before the first check
+                // isn't replaced, otherwise every restart would launch another one
+                let was_alive = last.as_ref().is_some_and(|s| !s.is_gone());
+                if let Some(recovery) = target.recovery.as_ref().filter(|_| instance.state.is_gone() && was_alive) {
+                    let launch = recovery.launch(Arc::clone(&self.alert), id, Some(instance.tags.clone()));
+                    target.launches.insert(id.clone(), launch);
+                }
+                if instance.state == State::Running {
+                    if let Some(launch) = target.launches.remove(id) {
+                        info!("controller - the instance {id} runs again, give up its replacement");
+                        launch.withdraw();
+                    }
+                }
+            }
+            for (old, new) in replaced {
+                info!("controller - watch {new} instead of {old}");
+                target.ids.retain(|id| *id != old);
+                target.ids.push(new);
+                seen.remove(&old);
+            }
+        }
+    }
+}
+
+// the host header sent by reqwest, which is signed as well
+fn host(endpoint: &str) -> Result<String, Box<dyn Error>> {
+    let url = reqwest::Url::parse(endpoint)?;
+    let host = url.host_str().ok_or(format!("no host in {endpoint}"))?;
+    Ok(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    })
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+// the lowercase hex of sha256
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // hmac accepts a key of any length
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac key");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alert::{AlertMap, Notice};
+    use std::sync::Mutex;
+
+    // the states of instances returned by a describe call
+    type Described = Vec<(&'static str, State)>;
+
+    // a fake cloud which returns the scripted results in order, and launches instances in memory
+    #[derive(Clone, Default)]
+    struct Fake {
+        results: Arc<Mutex<Vec<Described>>>,
+        launched: Arc<Mutex<Vec<(String, Tags)>>>,
+        terminated: Arc<Mutex<Vec<String>>>,
+    }
+
+    fn tags() -> Tags {
+        Tags::from([("project".to_string(), "swanlab".to_string())])
+    }
+
+    impl CloudApi for Fake {
+        fn code(&self) -> Code {
+            Code::AliCloudInterrupt
+        }
+
+        fn region(&self) -> String {
+            "cn-hangzhou".to_string()
+        }
+
+        fn describe(&self, ids: &[String]) -> Result<HashMap<String, Instance>, Box<dyn Error>> {
+            // the launched instances are running at once
+            if let Some((id, tags)) = self.launched.lock().unwrap().iter().find(|(id, _)| ids.contains(id)) {
+                let instance = Instance { state: State::Running, ip: Some("10.0.0.2".to_string()), tags: tags.clone() };
+                return Ok(HashMap::from([(id.clone(), instance)]));
+            }
+            let mut results = self.results.lock().unwrap();
+            if results.is_empty() {
+                return Err(Box::from("throttled"));
+            }
+            Ok(results
+                .remove(0)
+                .into_iter()
+                .map(|(id, state)| (id.to_string(), Instance { state, ip: None, tags: tags() }))
+                .collect())
+        }
+
+        fn run_instance(&self, template: &str, tags: &Tags) -> Result<String, Box<dyn Error>> {
+            if template != "lt-1" {
+                return Err(Box::from("InvalidLaunchTemplate.NotFound"));
+            }
+            let mut launched = self.launched.lock().unwrap();
+            let id = format!("i-new{}", launched.len());
+            launched.push((id.clone(), tags.clone()));
+            Ok(id)
+        }
+
+        fn terminate_instance(&self, id: &str) -> Result<(), Box<dyn Error>> {
+            self.terminated.lock().unwrap().push(id.to_string());
+            Ok(())
+        }
+    }
+
+    // the launched instances never show up
+    struct Slow(Fake);
+
+    impl CloudApi for Slow {
+        fn code(&self) -> Code {
+            self.0.code()
+        }
+
+        fn region(&self) -> String {
+            self.0.region()
+        }
+
+        fn describe(&self, _ids: &[String]) -> Result<HashMap<String, Instance>, Box<dyn Error>> {
+            Ok(HashMap::new())
+        }
+
+        fn run_instance(&self, template: &str, tags: &Tags) -> Result<String, Box<dyn Error>> {
+            self.0.run_instance(template, tags)
+        }
+
+        fn terminate_instance(&self, id: &str) -> Result<(), Box<dyn Error>> {
+            self.0.terminate_instance(id)
+        }
+    }
+
+    struct Recorder {
+        msgs: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Notice for Recorder {
+        fn send(&self, msg: &Msg) -> Result<(), Box<dyn Error>> {
+            self.msgs.lock().unwrap().push(format!("{} {}", msg.target(), msg.notes().join(",")));
+            Ok(())
+        }
+    }
+
+    fn recorder() -> (Arc<Alert>, Arc<Mutex<Vec<String>>>) {
+        let msgs = Arc::new(Mutex::new(vec![]));
+        let mut map = AlertMap::new();
+        map.insert("r".to_string(), Box::new(Recorder { msgs: Arc::clone(&msgs) }));
+        (Arc::new(Alert::new(map)), msgs)
+    }
+
+    fn script(results: Vec<Described>) -> Fake {
+        Fake { results: Arc::new(Mutex::new(results)), ..Default::default() }
+    }
+
+    #[test]
+    fn test_tick() {
+        let (alert, msgs) = recorder();
+        let fake = script(vec![
+            vec![("i-1", State::Running), ("i-2", State::Running)],
+            vec![("i-1", State::Recycling), ("i-2", State::Running)],
+            vec![("i-2", State::Running)],
+        ]);
+        let ids = vec!["i-1".to_string(), "i-2".to_string()];
+        let mut ctrl = Controller::new(0, alert).watch(Box::new(fake), ids, None);
+
+        let mut seen = HashMap::new();
+        // the last one fails, and nothing changes
+        for _ in 0..4 {
+            ctrl.tick(&mut seen);
+        }
+        assert_eq!(*msgs.lock().unwrap(), vec![
+            "another(i-1) 实例状态：回收中,所在地域：cn-hangzhou",
+            "another(i-1) 实例状态：已释放,所在地域：cn-hangzhou",
+        ]);
+        assert_eq!(seen.get("i-2").map(|i| &i.state), Some(&State::Running));
+    }
+
+    #[test]
+    fn test_tick_recover() {
+        let (alert, msgs) = recorder();
+        let fake = script(vec![
+            vec![("i-1", State::Running)],
+            vec![("i-1", State::Recycling)],
+            vec![],
+        ]);
+        let recovery = Recovery::new(Box::new(fake.clone()), "lt-1", Duration::from_secs(1));
+        let mut ctrl = Controller::new(0, alert).watch(Box::new(fake.clone()), vec!["i-1".to_string()], Some(recovery));
+
+        let mut seen = HashMap::new();
+        for _ in 0..4 {
+            ctrl.tick(&mut seen);
+            // the replacement is launched in the background
+            thread::sleep(Duration::from_millis(100));
+        }
+        // launched once with the same tags, then the replacement is watched after the release
+        assert_eq!(*fake.launched.lock().unwrap(), vec![("i-new0".to_string(), tags())]);
+        assert_eq!(ctrl.targets[0].ids, vec!["i-new0"]);
+        assert_eq!(*msgs.lock().unwrap(), vec![
+            "another(i-1) 实例状态：回收中,所在地域：cn-hangzhou",
+            "another(i-1) 原实例：i-1,新实例：i-new0,实例状态：运行中,IP 地址：10.0.0.2",
+            "another(i-1) 实例状态：已释放,所在地域：cn-hangzhou",
+        ]);
+        assert!(fake.terminated.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tick_withdraw() {
+        let (alert, _) = recorder();
+        let fake = script(vec![
+            vec![("i-1", State::Running)],
+            vec![("i-1", State::Recycling)],
+            vec![("i-1", State::Running)],
+        ]);
+        let recovery = Recovery::new(Box::new(fake.clone()), "lt-1", Duration::from_secs(1));
+        let mut ctrl = Controller::new(0, alert).watch(Box::new(fake.clone()), vec!["i-1".to_string()], Some(recovery));
+
+        let mut seen = HashMap::new();
+        for _ in 0..3 {
+            ctrl.tick(&mut seen);
+            thread::sleep(Duration::from_millis(100));
+        }
+        // the instance runs again, so the replacement is terminated and the instance is still watched
+        assert_eq!(*fake.terminated.lock().unwrap(), vec!["i-new0"]);
+        assert_eq!(ctrl.targets[0].ids, vec!["i-1"]);
+        assert!(ctrl.targets[0].launches.is_empty());
+    }
+
+    // released before the controller starts, e.g. after a restart or a wrong id
+    #[test]
+    fn test_tick_released_at_start() {
+        let (alert, msgs) = recorder();
+        let fake = script(vec![vec![], vec![]]);
+        let recovery = Recovery::new(Box::new(fake.clone()), "lt-1", Duration::from_secs(1));
+        let mut ctrl = Controller::new(0, alert).watch(Box::new(fake.clone()), vec!["i-1".to_string()], Some(recovery));
+
+        let mut seen = HashMap::new();
+        for _ in 0..2 {
+            ctrl.tick(&mut seen);
+            thread::sleep(Duration::from_millis(100));
+        }
+        // alerted, but never replaced
+        assert_eq!(*msgs.lock().unwrap(), vec!["another(i-1) 实例状态：已释放,所在地域：cn-hangzhou"]);
+        assert!(fake.launched.lock().unwrap().is_empty());
+        assert!(ctrl.targets[0].launches.is_empty());
+    }
+
+    #[test]
+    fn test_launch_withdrawn() {
+        let (alert, msgs) = recorder();
+        let fake = script(vec![]);
+        let terminated = Arc::clone(&fake.terminated);
+        // the replacement never runs
+        let recovery = Recovery::new(Box::new(Slow(fake)), "lt-1", Duration::from_secs(10)).with_poll(Duration::from_millis(10));
+        let launch = Arc::new(recovery).launch(alert, "i-1", Some(tags()));
+        thread::sleep(Duration::from_millis(50));
+        let launch = launch.try_finish().err().unwrap();
+        launch.withdraw().join().unwrap();
+        // terminated while waiting, and nothing is reported
+        assert_eq!(*terminated.lock().unwrap(), vec!["i-new0"]);
+        assert!(msgs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recover() {
+        let fake = script(vec![vec![("i-1", State::Recycling)]]);
+        let recovery = Recovery::new(Box::new(fake), "lt-1", Duration::from_secs(1));
+        // the tags are described at first
+        let r = recovery.recover("i-1", None).unwrap();
+        assert_eq!(r.id, "i-new0");
+        assert_eq!(r.instance.tags, tags());
+        assert_eq!(r.instance.ip, Some("10.0.0.2".to_string()));
+    }
+
+    #[test]
+    fn test_recover_timeout() {
+        let fake = script(vec![]);
+        let launched = Arc::clone(&fake.launched);
+        let recovery = Recovery::new(Box::new(Slow(fake)), "lt-1", Duration::ZERO).with_poll(Duration::ZERO);
+        let r = recovery.recover("i-1", Some(&tags())).unwrap();
+        assert_eq!(r.id, "i-new0");
+        assert_eq!(r.instance.state, State::Other("未知".to_string()));
+        assert_eq!(launched.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_report_failed() {
+        let (alert, msgs) = recorder();
+        let recovery = Recovery::new(Box::new(Fake::default()), "lt-404", Duration::from_secs(1));
+        assert_eq!(recovery.report(&alert, "i-1", Some(&tags())), None);
+        assert_eq!(*msgs.lock().unwrap(), vec![
+            "another(i-1) 原实例：i-1,失败原因：InvalidLaunchTemplate.NotFound",
+        ]);
+    }
+
+    #[test]
+    fn test_api() {
+        let account = |provider| config::Account {
+            provider,
+            region: "cn-hangzhou".to_string(),
+            secret_id: "id".to_string(),
+            secret_key: "key".to_string(),
+            instances: vec![],
+            endpoint: None,
+            launch_template: None,
+            launch_timeout: 300,
+            instance_types: vec![],
+        };
+        assert!(api(&account(config::Provider::AliCloud)).is_some());
+        assert!(api(&account(config::Provider::TencentCloud)).is_some());
+        assert!(api(&account(config::Provider::AwsEc2)).is_none());
+        assert!(recovery(&account(config::Provider::AliCloud)).is_none());
+        let with_template = config::Account {
+            launch_template: Some("lt-1".to_string()),
+            ..account(config::Provider::AliCloud)
+        };
+        assert!(recovery(&with_template).is_some());
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(host("https://cvm.tencentcloudapi.com").unwrap(), "cvm.tencentcloudapi.com");
+        assert_eq!(host("http://127.0.0.1:8080/").unwrap(), "127.0.0.1:8080");
+        assert!(host("not a url").is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
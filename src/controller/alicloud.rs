@@ -0,0 +1,607 @@
+use super::{hex, hmac_sha256, host, sha256_hex, CloudApi, Instance, SpotPrice, State, Tags};
+use crate::alert::Code;
+use crate::config::Account;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const VERSION: &str = "2014-05-26";
+
+// at most 100 instances in a request
+const PAGE_SIZE: usize = 100;
+
+// the price history is paged by offset, stop at some point in case of too many records
+const MAX_PRICE_PAGES: usize = 20;
+
+// the signed headers, in alphabetical order
+const SIGNED_HEADERS: &str = "host;x-acs-action;x-acs-content-sha256;x-acs-date;x-acs-signature-nonce;x-acs-version";
+
+// the response of DescribeInstances, only the fields we need
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct DescribeResponse {
+    instances: Instances,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Instances {
+    instance: Vec<EcsInstance>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EcsInstance {
+    instance_id: String,
+    status: String,
+    #[serde(default)]
+    operation_locks: Option<OperationLocks>,
+    #[serde(default)]
+    public_ip_address: Option<IpAddresses>,
+    #[serde(default)]
+    eip_address: Option<EipAddress>,
+    #[serde(default)]
+    vpc_attributes: Option<VpcAttributes>,
+    #[serde(default)]
+    tags: Option<EcsTags>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct OperationLocks {
+    #[serde(default)]
+    lock_reason: Vec<Lock>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Lock {
+    lock_reason: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct IpAddresses {
+    #[serde(default)]
+    ip_address: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EipAddress {
+    #[serde(default)]
+    ip_address: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct VpcAttributes {
+    private_ip_address: Option<IpAddresses>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EcsTags {
+    #[serde(default)]
+    tag: Vec<EcsTag>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EcsTag {
+    tag_key: String,
+    tag_value: String,
+}
+
+// the response of RunInstances
+// example: {"RequestId": "..", "InstanceIdSets": {"InstanceIdSet": ["i-bp67acfmxazb4pd2****"]}}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RunResponse {
+    instance_id_sets: InstanceIdSets,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct InstanceIdSets {
+    instance_id_set: Vec<String>,
+}
+
+// the response of DescribeSpotPriceHistory
+// example: {"NextOffset": 0, "Currency": "CNY", "SpotPrices": {"SpotPriceType": [
+//   {"ZoneId": "cn-hangzhou-i", "InstanceType": "ecs.g7.large", "SpotPrice": 0.12, "OriginPrice": 0.6, "Timestamp": "2024-09-12T07:00:00Z"}]}}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PriceResponse {
+    #[serde(default)]
+    next_offset: u64,
+    spot_prices: SpotPrices,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct SpotPrices {
+    #[serde(default)]
+    spot_price_type: Vec<PriceType>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PriceType {
+    zone_id: String,
+    instance_type: String,
+    spot_price: f64,
+    origin_price: Option<f64>,
+    timestamp: String,
+}
+
+impl EcsInstance {
+    // a reclaimed spot instance is locked with the reason `Recycling`
+    fn state(&self) -> State {
+        let recycling = self
+            .operation_locks
+            .as_ref()
+            .is_some_and(|l| l.lock_reason.iter().any(|r| r.lock_reason == "Recycling"));
+        match self.status.as_str() {
+            _ if recycling => State::Recycling,
+            "Running" => State::Running,
+            "Stopped" => State::Stopped,
+            s => State::Other(s.to_string()),
+        }
+    }
+
+    // the public ip, the elastic ip, or the private ip in vpc
+    fn ip(&self) -> Option<String> {
+        let public = self.public_ip_address.as_ref().and_then(|a| a.ip_address.first().cloned());
+        let eip = self.eip_address.as_ref().map(|a| a.ip_address.clone()).filter(|a| !a.is_empty());
+        let private = self
+            .vpc_attributes
+            .as_ref()
+            .and_then(|v| v.private_ip_address.as_ref())
+            .and_then(|a| a.ip_address.first().cloned());
+        public.or(eip).or(private)
+    }
+
+    fn into_instance(self) -> (String, Instance) {
+        let instance = Instance {
+            state: self.state(),
+            ip: self.ip(),
+            tags: self
+                .tags
+                .map(|t| t.tag.into_iter().map(|t| (t.tag_key, t.tag_value)).collect())
+                .unwrap_or_default(),
+        };
+        (self.instance_id, instance)
+    }
+}
+
+// the ecs api of aliyun, signed with ACS3-HMAC-SHA256 (signature v3)
+// reference: https://help.aliyun.com/zh/ecs/developer-reference/api-ecs-2014-05-26-describeinstances
+// reference: https://help.aliyun.com/zh/ecs/developer-reference/api-ecs-2014-05-26-runinstances
+// reference: https://help.aliyun.com/zh/ecs/developer-reference/api-ecs-2014-05-26-deleteinstance
+// reference: https://help.aliyun.com/zh/sdk/product-overview/v3-request-structure-and-signature
+pub struct AliCloudApi {
+    client: Client,
+    endpoint: String,
+    region: String,
+    secret_id: String,
+    secret_key: String,
+}
+
+impl AliCloudApi {
+    pub fn new(account: &Account) -> AliCloudApi {
+        let endpoint = account
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://ecs.{}.aliyuncs.com", account.region));
+        AliCloudApi {
+            client: Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            region: account.region.clone(),
+            secret_id: account.secret_id.clone(),
+            secret_key: account.secret_key.clone(),
+        }
+    }
+
+    // POST /?RegionId=..&{params}, the parameters are in the query string, and the body is empty
+    fn call(&self, action: &str, params: Vec<(String, String)>) -> Result<String, Box<dyn Error>> {
+        let mut params = params;
+        params.push(("RegionId".to_string(), self.region.clone()));
+        let query = canonical_query(&params);
+        let host = host(&self.endpoint)?;
+        let date = Utc::now();
+        let payload = sha256_hex(b"");
+        let headers = headers(host, action, &payload, &date, nonce(&date));
+        let canonical = canonical_request("POST", &query, &headers, &payload);
+        let authorization = format!(
+            "ACS3-HMAC-SHA256 Credential={},SignedHeaders={SIGNED_HEADERS},Signature={}",
+            self.secret_id,
+            sign(&self.secret_key, &canonical)
+        );
+
+        let mut req = self
+            .client
+            .post(format!("{}/?{query}", self.endpoint))
+            .header("Authorization", authorization);
+        for (k, v) in headers.iter().filter(|(k, _)| *k != "host") {
+            req = req.header(*k, v);
+        }
+        let res = req.send()?;
+        let status = res.status();
+        let text = res.text()?;
+        if !status.is_success() {
+            return Err(Box::from(format!("{status}: {text}")));
+        }
+        Ok(text)
+    }
+}
+
+impl CloudApi for AliCloudApi {
+    fn code(&self) -> Code {
+        Code::AliCloudInterrupt
+    }
+
+    fn region(&self) -> String {
+        self.region.clone()
+    }
+
+    fn describe(&self, ids: &[String]) -> Result<HashMap<String, Instance>, Box<dyn Error>> {
+        let mut instances = HashMap::new();
+        for page in ids.chunks(PAGE_SIZE) {
+            let text = self.call("DescribeInstances", vec![
+                ("InstanceIds".to_string(), serde_json::to_string(page)?),
+                ("PageSize".to_string(), PAGE_SIZE.to_string()),
+            ])?;
+            let res: DescribeResponse = serde_json::from_str(&text)?;
+            instances.extend(res.instances.instance.into_iter().map(|i| i.into_instance()));
+        }
+        Ok(instances)
+    }
+
+    // the tags are passed as Tag.N.Key and Tag.N.Value, N starts from 1
+    fn run_instance(&self, template: &str, tags: &Tags) -> Result<String, Box<dyn Error>> {
+        let mut params = vec![
+            ("LaunchTemplateId".to_string(), template.to_string()),
+            ("Amount".to_string(), "1".to_string()),
+        ];
+        for (n, (k, v)) in tags.iter().enumerate() {
+            params.push((format!("Tag.{}.Key", n + 1), k.clone()));
+            params.push((format!("Tag.{}.Value", n + 1), v.clone()));
+        }
+        let text = self.call("RunInstances", params)?;
+        let res: RunResponse = serde_json::from_str(&text)?;
+        res.instance_id_sets
+            .instance_id_set
+            .into_iter()
+            .next()
+            .ok_or(Box::from(format!("no instance launched: {text}")))
+    }
+
+    // a running instance is released as well
+    fn terminate_instance(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.call("DeleteInstance", vec![
+            ("InstanceId".to_string(), id.to_string()),
+            ("Force".to_string(), "true".to_string()),
+        ])?;
+        Ok(())
+    }
+
+    // the history is at most 30 days, and only one instance type is queried in a request
+    fn spot_prices(&self, instance_types: &[String], since: DateTime<Utc>) -> Result<Vec<SpotPrice>, Box<dyn Error>> {
+        let mut prices = vec![];
+        for instance_type in instance_types {
+            let mut offset = 0;
+            let mut points = vec![];
+            for _ in 0..MAX_PRICE_PAGES {
+                let text = self.call("DescribeSpotPriceHistory", vec![
+                    ("NetworkType".to_string(), "vpc".to_string()),
+                    ("InstanceType".to_string(), instance_type.clone()),
+                    ("StartTime".to_string(), since.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+                    ("Offset".to_string(), offset.to_string()),
+                ])?;
+                let res: PriceResponse = serde_json::from_str(&text)?;
+                let empty = res.spot_prices.spot_price_type.is_empty();
+                points.extend(res.spot_prices.spot_price_type);
+                if empty || res.next_offset <= offset {
+                    break;
+                }
+                offset = res.next_offset;
+            }
+            // group by zone, the timestamps are in the same format
+            points.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+            let mut zones: BTreeMap<String, SpotPrice> = BTreeMap::new();
+            for p in points {
+                let price = zones.entry(p.zone_id.clone()).or_insert_with(|| SpotPrice {
+                    zone: p.zone_id,
+                    instance_type: p.instance_type,
+                    prices: vec![],
+                    origin: None,
+                });
+                price.prices.push(p.spot_price);
+                price.origin = p.origin_price.or(price.origin);
+            }
+            prices.extend(zones.into_values());
+        }
+        Ok(prices)
+    }
+}
+
+// the signature nonce must be unique in a short time
+fn nonce(date: &DateTime<Utc>) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{}{n}", date.timestamp_nanos_opt().unwrap_or_default())
+}
+
+// the signed headers, in the order of `SIGNED_HEADERS`
+fn headers(host: String, action: &str, payload: &str, date: &DateTime<Utc>, nonce: String) -> [(&'static str, String); 6] {
+    [
+        ("host", host),
+        ("x-acs-action", action.to_string()),
+        ("x-acs-content-sha256", payload.to_string()),
+        ("x-acs-date", date.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+        ("x-acs-signature-nonce", nonce),
+        ("x-acs-version", VERSION.to_string()),
+    ]
+}
+
+// sorted by key, and both key and value are percent-encoded
+fn canonical_query(params: &[(String, String)]) -> String {
+    let mut params: Vec<String> = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode(k), encode(v)))
+        .collect();
+    params.sort();
+    params.join("&")
+}
+
+// the headers must be lowercase and sorted, the path is always `/` for the rpc style
+fn canonical_request(method: &str, query: &str, headers: &[(&str, String)], payload: &str) -> String {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(k, v)| format!("{k}:{}\n", v.trim()))
+        .collect();
+    format!("{method}\n/\n{query}\n{canonical_headers}\n{SIGNED_HEADERS}\n{payload}")
+}
+
+fn sign(secret_key: &str, canonical: &str) -> String {
+    let string_to_sign = format!("ACS3-HMAC-SHA256\n{}", sha256_hex(canonical.as_bytes()));
+    hex(&hmac_sha256(secret_key.as_bytes(), string_to_sign.as_bytes()))
+}
+
+// RFC 3986, only the unreserved characters are kept
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Provider;
+    use chrono::TimeZone;
+    use mockito::{Matcher, Server};
+
+    fn account(endpoint: &str) -> Account {
+        Account {
+            provider: Provider::AliCloud,
+            region: "cn-hangzhou".to_string(),
+            secret_id: "LTAIEXAMPLE".to_string(),
+            secret_key: "secret".to_string(),
+            instances: vec![],
+            endpoint: Some(endpoint.to_string()),
+            launch_template: None,
+            launch_timeout: 300,
+            instance_types: vec![],
+        }
+    }
+
+    #[test]
+    fn test_canonical_request() {
+        let query = canonical_query(&[
+            ("RegionId".to_string(), "cn-hangzhou".to_string()),
+            ("InstanceIds".to_string(), r#"["i-1"]"#.to_string()),
+        ]);
+        assert_eq!(query, "InstanceIds=%5B%22i-1%22%5D&RegionId=cn-hangzhou");
+        let headers = [
+            ("host", "ecs.cn-hangzhou.aliyuncs.com".to_string()),
+            ("x-acs-action", "DescribeInstances".to_string()),
+        ];
+        assert_eq!(
+            canonical_request("POST", &query, &headers, "e3b0"),
+            format!(
+                "POST\n/\n{query}\nhost:ecs.cn-hangzhou.aliyuncs.com\nx-acs-action:DescribeInstances\n\n{SIGNED_HEADERS}\ne3b0"
+            )
+        );
+        // the same request always has the same signature
+        assert_eq!(sign("secret", "a"), sign("secret", "a"));
+        assert_ne!(sign("secret", "a"), sign("other", "a"));
+    }
+
+    // the example of the v3 signature in the documents of alibaba cloud
+    #[test]
+    fn test_signature_example() {
+        let query = canonical_query(&[
+            ("RegionId".to_string(), "cn-shanghai".to_string()),
+            ("ImageId".to_string(), "win2019_1809_x64_dtc_zh-cn_40G_alibase_20230811.vhd".to_string()),
+        ]);
+        let payload = sha256_hex(b"");
+        let date = Utc.with_ymd_and_hms(2023, 10, 26, 10, 22, 32).unwrap();
+        let nonce = "3156853299f313e23d1673dc12e1703d".to_string();
+        let headers = headers("ecs.cn-shanghai.aliyuncs.com".to_string(), "RunInstances", &payload, &date, nonce);
+        let canonical = canonical_request("POST", &query, &headers, &payload);
+        assert_eq!(
+            canonical,
+            "POST\n/\nImageId=win2019_1809_x64_dtc_zh-cn_40G_alibase_20230811.vhd&RegionId=cn-shanghai\n\
+             host:ecs.cn-shanghai.aliyuncs.com\n\
+             x-acs-action:RunInstances\n\
+             x-acs-content-sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n\
+             x-acs-date:2023-10-26T10:22:32Z\n\
+             x-acs-signature-nonce:3156853299f313e23d1673dc12e1703d\n\
+             x-acs-version:2014-05-26\n\n\
+             host;x-acs-action;x-acs-content-sha256;x-acs-date;x-acs-signature-nonce;x-acs-version\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(sha256_hex(canonical.as_bytes()), "7ea06492da5221eba5297e897ce16e55f964061054b7695beedaac1145b1e259");
+        assert_eq!(
+            sign("YourAccessKeySecret", &canonical),
+            "06563a9e1b43f5dfe96b81484da74bceab24a1d853912eee15083a6f0f3283c0"
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("RegionId".into(), "cn-hangzhou".into()),
+                Matcher::UrlEncoded("InstanceIds".into(), r#"["i-1","i-2","i-3"]"#.into()),
+            ]))
+            .match_header("x-acs-action", "DescribeInstances")
+            .match_header("x-acs-version", VERSION)
+            .match_header(
+                "Authorization",
+                Matcher::Regex(format!(
+                    "^ACS3-HMAC-SHA256 Credential=LTAIEXAMPLE,SignedHeaders={SIGNED_HEADERS},Signature=[0-9a-f]{{64}}$"
+                )),
+            )
+            .with_body(
+                r#"{"RequestId": "x", "TotalCount": 2, "Instances": {"Instance": [
+                    {"InstanceId": "i-1", "Status": "Running", "OperationLocks": {"LockReason": []},
+                     "PublicIpAddress": {"IpAddress": []}, "EipAddress": {"IpAddress": ""},
+                     "VpcAttributes": {"PrivateIpAddress": {"IpAddress": ["172.16.0.1"]}},
+                     "Tags": {"Tag": [{"TagKey": "project", "TagValue": "swanlab"}]}},
+                    {"InstanceId": "i-2", "Status": "Stopped", "OperationLocks": {"LockReason": [{"LockReason": "Recycling"}]}}
+                ]}}"#,
+            )
+            .create();
+
+        let api = AliCloudApi::new(&account(&server.url()));
+        let ids = vec!["i-1".to_string(), "i-2".to_string(), "i-3".to_string()];
+        let instances = api.describe(&ids).unwrap();
+        assert_eq!(instances.get("i-1"), Some(&Instance {
+            state: State::Running,
+            ip: Some("172.16.0.1".to_string()),
+            tags: Tags::from([("project".to_string(), "swanlab".to_string())]),
+        }));
+        assert_eq!(instances.get("i-2").map(|i| &i.state), Some(&State::Recycling));
+        // released, the controller takes care of it
+        assert_eq!(instances.get("i-3"), None);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_run_instance() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("LaunchTemplateId".into(), "lt-bp1".into()),
+                Matcher::UrlEncoded("Amount".into(), "1".into()),
+                Matcher::UrlEncoded("Tag.1.Key".into(), "project".into()),
+                Matcher::UrlEncoded("Tag.1.Value".into(), "swanlab".into()),
+            ]))
+            .match_header("x-acs-action", "RunInstances")
+            .with_body(r#"{"RequestId": "x", "InstanceIdSets": {"InstanceIdSet": ["i-bp67acfmxazb4pd2"]}}"#)
+            .create();
+
+        let api = AliCloudApi::new(&account(&server.url()));
+        let tags = Tags::from([("project".to_string(), "swanlab".to_string())]);
+        assert_eq!(api.run_instance("lt-bp1", &tags).unwrap(), "i-bp67acfmxazb4pd2");
+        mock.assert();
+    }
+
+    #[test]
+    fn test_terminate_instance() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("InstanceId".into(), "i-bp67acfmxazb4pd2".into()),
+                Matcher::UrlEncoded("Force".into(), "true".into()),
+            ]))
+            .match_header("x-acs-action", "DeleteInstance")
+            .with_body(r#"{"RequestId": "x"}"#)
+            .create();
+
+        let api = AliCloudApi::new(&account(&server.url()));
+        api.terminate_instance("i-bp67acfmxazb4pd2").unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_spot_prices() {
+        let mut server = Server::new();
+        let first = server
+            .mock("POST", "/")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("InstanceType".into(), "ecs.g7.large".into()),
+                Matcher::UrlEncoded("NetworkType".into(), "vpc".into()),
+                Matcher::UrlEncoded("StartTime".into(), "2024-09-01T00:00:00Z".into()),
+                Matcher::UrlEncoded("Offset".into(), "0".into()),
+            ]))
+            .match_header("x-acs-action", "DescribeSpotPriceHistory")
+            .with_body(r#"{"NextOffset": 2, "Currency": "CNY", "SpotPrices": {"SpotPriceType": [
+                {"ZoneId": "cn-hangzhou-i", "InstanceType": "ecs.g7.large", "SpotPrice": 0.2, "OriginPrice": 0.6, "Timestamp": "2024-09-02T00:00:00Z"},
+                {"ZoneId": "cn-hangzhou-j", "InstanceType": "ecs.g7.large", "SpotPrice": 0.3, "OriginPrice": 0.6, "Timestamp": "2024-09-01T00:00:00Z"}
+            ]}}"#)
+            .create();
+        let second = server
+            .mock("POST", "/")
+            .match_query(Matcher::UrlEncoded("Offset".into(), "2".into()))
+            .with_body(r#"{"NextOffset": 3, "SpotPrices": {"SpotPriceType": [
+                {"ZoneId": "cn-hangzhou-i", "InstanceType": "ecs.g7.large", "SpotPrice": 0.1, "OriginPrice": 0.6, "Timestamp": "2024-09-01T00:00:00Z"}
+            ]}}"#)
+            .create();
+        let last = server
+            .mock("POST", "/")
+            .match_query(Matcher::UrlEncoded("Offset".into(), "3".into()))
+            .with_body(r#"{"NextOffset": 0, "SpotPrices": {"SpotPriceType": []}}"#)
+            .create();
+
+        let api = AliCloudApi::new(&account(&server.url()));
+        let since = Utc.with_ymd_and_hms(2024, 9, 1, 0, 0, 0).unwrap();
+        let prices = api.spot_prices(&["ecs.g7.large".to_string()], since).unwrap();
+        assert_eq!(prices, vec![
+            SpotPrice {
+                zone: "cn-hangzhou-i".to_string(),
+                instance_type: "ecs.g7.large".to_string(),
+                prices: vec![0.1, 0.2],
+                origin: Some(0.6),
+            },
+            SpotPrice {
+                zone: "cn-hangzhou-j".to_string(),
+                instance_type: "ecs.g7.large".to_string(),
+                prices: vec![0.3],
+                origin: Some(0.6),
+            },
+        ]);
+        first.assert();
+        second.assert();
+        last.assert();
+    }
+
+    #[test]
+    fn test_describe_err() {
+        let mut server = Server::new();
+        server
+            .mock("POST", "/")
+            .match_query(Matcher::Any)
+            .with_status(403)
+            .with_body(r#"{"Code": "IncompleteSignature", "Message": "The request signature does not conform to Aliyun standards."}"#)
+            .create();
+
+        let api = AliCloudApi::new(&account(&server.url()));
+        let err = api.describe(&["i-1".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("IncompleteSignature"));
+    }
+}
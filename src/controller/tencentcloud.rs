@@ -0,0 +1,454 @@
+use super::{hex, hmac_sha256, host, sha256_hex, CloudApi, Instance, SpotPrice, State, Tags};
+use crate::alert::Code;
+use crate::config::Account;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+const SERVICE: &str = "cvm";
+const VERSION: &str = "2017-03-12";
+const CONTENT_TYPE: &str = "application/json; charset=utf-8";
+const SIGNED_HEADERS: &str = "content-type;host;x-tc-action";
+
+// at most 100 instances in a request
+const LIMIT: usize = 100;
+
+// the errors are returned with 200
+// example: {"Response": {"Error": {"Code": "AuthFailure", "Message": ".."}, "RequestId": ".."}}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ApiError {
+    code: String,
+    message: String,
+}
+
+// the response of DescribeInstances, only the fields we need
+// example: {"Response": {"TotalCount": 1, "InstanceSet": [{"InstanceId": "ins-1", "InstanceState": "RUNNING"}]}}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct DescribeResponse {
+    #[serde(default)]
+    instance_set: Vec<CvmInstance>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct CvmInstance {
+    instance_id: String,
+    instance_state: String,
+    #[serde(default)]
+    public_ip_addresses: Option<Vec<String>>,
+    #[serde(default)]
+    private_ip_addresses: Option<Vec<String>>,
+    #[serde(default)]
+    tags: Option<Vec<CvmTag>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct CvmTag {
+    key: String,
+    value: String,
+}
+
+// the response of RunInstances
+// example: {"Response": {"InstanceIdSet": ["ins-1"], "RequestId": ".."}}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RunResponse {
+    instance_id_set: Vec<String>,
+}
+
+// the response of DescribeZoneInstanceConfigInfos, only the fields we need
+// example: {"Response": {"InstanceTypeQuotaSet": [{"Zone": "ap-guangzhou-3", "InstanceType": "S5.MEDIUM2",
+//   "Price": {"UnitPrice": 0.31, "UnitPriceDiscount": 0.06, "ChargeUnit": "HOUR"}}]}}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct QuotaResponse {
+    #[serde(default)]
+    instance_type_quota_set: Vec<Quota>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Quota {
+    zone: String,
+    instance_type: String,
+    price: QuotaPrice,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct QuotaPrice {
+    unit_price: Option<f64>,
+    unit_price_discount: Option<f64>,
+}
+
+impl CvmInstance {
+    // a reclaimed spot instance is shut down, then terminated
+    fn state(&self) -> State {
+        match self.instance_state.as_str() {
+            "RUNNING" => State::Running,
+            "STOPPED" => State::Stopped,
+            "SHUTDOWN" | "TERMINATING" => State::Recycling,
+            s => State::Other(s.to_string()),
+        }
+    }
+
+    fn into_instance(self) -> (String, Instance) {
+        let first = |ips: Option<Vec<String>>| ips.and_then(|i| i.into_iter().next());
+        let instance = Instance {
+            state: self.state(),
+            ip: first(self.public_ip_addresses).or(first(self.private_ip_addresses)),
+            tags: self
+                .tags
+                .unwrap_or_default()
+                .into_iter()
+                .map(|t| (t.key, t.value))
+                .collect(),
+        };
+        (self.instance_id, instance)
+    }
+}
+
+// the cvm api of tencent cloud, signed with TC3-HMAC-SHA256
+// reference: https://cloud.tencent.com/document/api/213/15728
+// reference: https://cloud.tencent.com/document/api/213/15730
+// reference: https://cloud.tencent.com/document/api/213/15723
+// reference: https://cloud.tencent.com/document/api/213/30654
+pub struct TencentCloudApi {
+    client: Client,
+    endpoint: String,
+    region: String,
+    secret_id: String,
+    secret_key: String,
+}
+
+impl TencentCloudApi {
+    pub fn new(account: &Account) -> TencentCloudApi {
+        let endpoint = account
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://{SERVICE}.tencentcloudapi.com"));
+        TencentCloudApi {
+            client: Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            region: account.region.clone(),
+            secret_id: account.secret_id.clone(),
+            secret_key: account.secret_key.clone(),
+        }
+    }
+
+    // POST / with a json body, the action and version are in the headers
+    fn call<T: DeserializeOwned>(&self, action: &str, payload: Value) -> Result<T, Box<dyn Error>> {
+        let payload = payload.to_string();
+        let host = host(&self.endpoint)?;
+        let now = Utc::now();
+        let authorization = self.authorization(&host, action, &payload, &now);
+
+        let res = self
+            .client
+            .post(format!("{}/", self.endpoint))
+            .header("Authorization", authorization)
+            .header("Content-Type", CONTENT_TYPE)
+            .header("X-TC-Action", action)
+            .header("X-TC-Timestamp", now.timestamp().to_string())
+            .header("X-TC-Version", VERSION)
+            .header("X-TC-Region", &self.region)
+            .body(payload)
+            .send()?;
+        let status = res.status();
+        let text = res.text()?;
+        if !status.is_success() {
+            return Err(Box::from(format!("{status}: {text}")));
+        }
+        let mut body: Value = serde_json::from_str(&text)?;
+        let response = body.get_mut("Response").map(Value::take).unwrap_or_default();
+        if let Some(e) = response.get("Error") {
+            let e: ApiError = serde_json::from_value(e.clone())?;
+            return Err(Box::from(format!("{}: {}", e.code, e.message)));
+        }
+        Ok(serde_json::from_value(response)?)
+    }
+
+    fn authorization(&self, host: &str, action: &str, payload: &str, now: &DateTime<Utc>) -> String {
+        let date = now.format("%Y-%m-%d").to_string();
+        let scope = format!("{date}/{SERVICE}/tc3_request");
+        let canonical = canonical_request(host, action, payload);
+        let string_to_sign = string_to_sign(now, &scope, &canonical);
+        format!(
+            "TC3-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={SIGNED_HEADERS}, Signature={}",
+            self.secret_id,
+            sign(&self.secret_key, &date, &string_to_sign)
+        )
+    }
+}
+
+impl CloudApi for TencentCloudApi {
+    fn code(&self) -> Code {
+        Code::TencentCloudInterrupt
+    }
+
+    fn region(&self) -> String {
+        self.region.clone()
+    }
+
+    fn describe(&self, ids: &[String]) -> Result<HashMap<String, Instance>, Box<dyn Error>> {
+        let mut instances = HashMap::new();
+        for page in ids.chunks(LIMIT) {
+            let res: DescribeResponse = self.call("DescribeInstances", json!({"InstanceIds": page, "Limit": LIMIT}))?;
+            instances.extend(res.instance_set.into_iter().map(|i| i.into_instance()));
+        }
+        Ok(instances)
+    }
+
+    fn run_instance(&self, template: &str, tags: &Tags) -> Result<String, Box<dyn Error>> {
+        let tags: Vec<Value> = tags.iter().map(|(k, v)| json!({"Key": k, "Value": v})).collect();
+        let mut payload = json!({
+            "LaunchTemplate": {"LaunchTemplateId": template},
+            "InstanceCount": 1,
+        });
+        if !tags.is_empty() {
+            payload["TagSpecification"] = json!([{"ResourceType": "instance", "Tags": tags}]);
+        }
+        let res: RunResponse = self.call("RunInstances", payload)?;
+        res.instance_id_set
+            .into_iter()
+            .next()
+            .ok_or(Box::from("no instance launched"))
+    }
+
+    fn terminate_instance(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let _: Value = self.call("TerminateInstances", json!({"InstanceIds": [id]}))?;
+        Ok(())
+    }
+
+    // tencent cloud doesn't provide the price history, so only the current price is returned
+    fn spot_prices(&self, instance_types: &[String], _since: DateTime<Utc>) -> Result<Vec<SpotPrice>, Box<dyn Error>> {
+        let res: QuotaResponse = self.call(
+            "DescribeZoneInstanceConfigInfos",
+            json!({"Filters": [
+                {"Name": "instance-charge-type", "Values": ["SPOTPAID"]},
+                {"Name": "instance-type", "Values": instance_types},
+            ]}),
+        )?;
+        Ok(res
+            .instance_type_quota_set
+            .into_iter()
+            .filter_map(|q| {
+                let price = q.price.unit_price_discount?;
+                Some(SpotPrice {
+                    zone: q.zone,
+                    instance_type: q.instance_type,
+                    prices: vec![price],
+                    origin: q.price.unit_price,
+                })
+            })
+            .collect())
+    }
+}
+
+// the path is always `/`, and there is no query string for POST
+fn canonical_request(host: &str, action: &str, payload: &str) -> String {
+    format!(
+        "POST\n/\n\ncontent-type:{CONTENT_TYPE}\nhost:{host}\nx-tc-action:{}\n\n{SIGNED_HEADERS}\n{}",
+        action.to_lowercase(),
+        sha256_hex(payload.as_bytes())
+    )
+}
+
+fn string_to_sign(now: &DateTime<Utc>, scope: &str, canonical: &str) -> String {
+    format!("TC3-HMAC-SHA256\n{}\n{scope}\n{}", now.timestamp(), sha256_hex(canonical.as_bytes()))
+}
+
+// the signing key is derived from the secret key, the date and the service
+fn sign(secret_key: &str, date: &str, string_to_sign: &str) -> String {
+    let secret_date = hmac_sha256(format!("TC3{secret_key}").as_bytes(), date.as_bytes());
+    let secret_service = hmac_sha256(&secret_date, SERVICE.as_bytes());
+    let secret_signing = hmac_sha256(&secret_service, b"tc3_request");
+    hex(&hmac_sha256(&secret_signing, string_to_sign.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Provider;
+    use chrono::TimeZone;
+    use mockito::{Matcher, Server};
+
+    fn account(endpoint: &str) -> Account {
+        Account {
+            provider: Provider::TencentCloud,
+            region: "ap-guangzhou".to_string(),
+            secret_id: "AKIDEXAMPLE".to_string(),
+            secret_key: "secret".to_string(),
+            instances: vec![],
+            endpoint: Some(endpoint.to_string()),
+            launch_template: None,
+            launch_timeout: 300,
+            instance_types: vec![],
+        }
+    }
+
+    #[test]
+    fn test_authorization() {
+        let api = TencentCloudApi::new(&account("https://cvm.tencentcloudapi.com"));
+        assert_eq!(
+            canonical_request("cvm.tencentcloudapi.com", "DescribeInstances", ""),
+            "POST\n/\n\ncontent-type:application/json; charset=utf-8\nhost:cvm.tencentcloudapi.com\n\
+             x-tc-action:describeinstances\n\ncontent-type;host;x-tc-action\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let now = Utc.with_ymd_and_hms(2019, 2, 25, 8, 44, 25).unwrap();
+        let authorization = api.authorization("cvm.tencentcloudapi.com", "DescribeInstances", "{}", &now);
+        assert!(authorization.starts_with(
+            "TC3-HMAC-SHA256 Credential=AKIDEXAMPLE/2019-02-25/cvm/tc3_request, \
+             SignedHeaders=content-type;host;x-tc-action, Signature="
+        ));
+        // the signature changes with the date
+        assert_ne!(sign("secret", "2019-02-25", "a"), sign("secret", "2019-02-26", "a"));
+    }
+
+    // the example of the tc3 signature in the documents of tencent cloud, whose signed headers are
+    // `content-type;host` only, so the canonical request is taken from the documents as well
+    #[test]
+    fn test_signature_example() {
+        let payload = r#"{"Limit": 1, "Filters": [{"Values": ["\u672a\u547d\u540d"], "Name": "instance-name"}]}"#;
+        let hashed_payload = "35e9c5b0e3ae67532d3c9f17ead6c90222632e5b1ff7f6e89887f1398934f064";
+        assert!(canonical_request("cvm.tencentcloudapi.com", "DescribeInstances", payload).ends_with(hashed_payload));
+        let canonical = format!(
+            "POST\n/\n\ncontent-type:application/json; charset=utf-8\nhost:cvm.tencentcloudapi.com\n\n\
+             content-type;host\n{hashed_payload}"
+        );
+        let now = DateTime::from_timestamp(1551113065, 0).unwrap();
+        let string_to_sign = string_to_sign(&now, "2019-02-25/cvm/tc3_request", &canonical);
+        assert_eq!(
+            string_to_sign,
+            "TC3-HMAC-SHA256\n1551113065\n2019-02-25/cvm/tc3_request\n\
+             5ffe6a04c0664d6b969fab9a13bdab201d63ee709638e2749d62a09ca18d7031"
+        );
+        assert_eq!(
+            sign("Gu5t9xGARNpq86cd98joQYCN3EXAMPLE", "2019-02-25", &string_to_sign),
+            "72e494ea809ad7a8c8f7a4507b9bddcbaa8e581f516e8da2f66e2c5a96525168"
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("X-TC-Action", "DescribeInstances")
+            .match_header("X-TC-Version", VERSION)
+            .match_header("X-TC-Region", "ap-guangzhou")
+            .match_header(
+                "Authorization",
+                Matcher::Regex(
+                    r"^TC3-HMAC-SHA256 Credential=AKIDEXAMPLE/\d{4}-\d{2}-\d{2}/cvm/tc3_request, SignedHeaders=content-type;host;x-tc-action, Signature=[0-9a-f]{64}$"
+                        .to_string(),
+                ),
+            )
+            .match_body(Matcher::PartialJson(json!({"InstanceIds": ["ins-1", "ins-2", "ins-3"]})))
+            .with_body(
+                r#"{"Response": {"TotalCount": 2, "InstanceSet": [
+                    {"InstanceId": "ins-1", "InstanceState": "RUNNING", "PublicIpAddresses": ["1.2.3.4"],
+                     "PrivateIpAddresses": ["10.0.0.1"], "Tags": [{"Key": "project", "Value": "swanlab"}]},
+                    {"InstanceId": "ins-2", "InstanceState": "SHUTDOWN"}
+                ], "RequestId": "x"}}"#,
+            )
+            .create();
+
+        let api = TencentCloudApi::new(&account(&server.url()));
+        let ids = vec!["ins-1".to_string(), "ins-2".to_string(), "ins-3".to_string()];
+        let instances = api.describe(&ids).unwrap();
+        assert_eq!(instances.get("ins-1"), Some(&Instance {
+            state: State::Running,
+            ip: Some("1.2.3.4".to_string()),
+            tags: Tags::from([("project".to_string(), "swanlab".to_string())]),
+        }));
+        assert_eq!(instances.get("ins-2").map(|i| &i.state), Some(&State::Recycling));
+        assert_eq!(instances.get("ins-3"), None);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_run_instance() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("X-TC-Action", "RunInstances")
+            .match_body(Matcher::Json(json!({
+                "LaunchTemplate": {"LaunchTemplateId": "lt-1"},
+                "InstanceCount": 1,
+                "TagSpecification": [{"ResourceType": "instance", "Tags": [{"Key": "project", "Value": "swanlab"}]}],
+            })))
+            .with_body(r#"{"Response": {"InstanceIdSet": ["ins-new"], "RequestId": "x"}}"#)
+            .create();
+
+        let api = TencentCloudApi::new(&account(&server.url()));
+        let tags = Tags::from([("project".to_string(), "swanlab".to_string())]);
+        assert_eq!(api.run_instance("lt-1", &tags).unwrap(), "ins-new");
+        mock.assert();
+    }
+
+    #[test]
+    fn test_terminate_instance() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("X-TC-Action", "TerminateInstances")
+            .match_body(Matcher::Json(json!({"InstanceIds": ["ins-new"]})))
+            .with_body(r#"{"Response": {"RequestId": "x"}}"#)
+            .create();
+
+        let api = TencentCloudApi::new(&account(&server.url()));
+        api.terminate_instance("ins-new").unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_spot_prices() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("X-TC-Action", "DescribeZoneInstanceConfigInfos")
+            .match_body(Matcher::PartialJson(json!({"Filters": [
+                {"Name": "instance-charge-type", "Values": ["SPOTPAID"]},
+                {"Name": "instance-type", "Values": ["S5.MEDIUM2"]},
+            ]})))
+            .with_body(r#"{"Response": {"InstanceTypeQuotaSet": [
+                {"Zone": "ap-guangzhou-3", "InstanceType": "S5.MEDIUM2", "Price": {"UnitPrice": 0.31, "UnitPriceDiscount": 0.06, "ChargeUnit": "HOUR"}},
+                {"Zone": "ap-guangzhou-4", "InstanceType": "S5.MEDIUM2", "Price": {}}
+            ], "RequestId": "x"}}"#)
+            .create();
+
+        let api = TencentCloudApi::new(&account(&server.url()));
+        let prices = api.spot_prices(&["S5.MEDIUM2".to_string()], Utc::now()).unwrap();
+        // the zone without price is skipped
+        assert_eq!(prices, vec![SpotPrice {
+            zone: "ap-guangzhou-3".to_string(),
+            instance_type: "S5.MEDIUM2".to_string(),
+            prices: vec![0.06],
+            origin: Some(0.31),
+        }]);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_describe_err() {
+        let mut server = Server::new();
+        server
+            .mock("POST", "/")
+            .with_body(r#"{"Response": {"Error": {"Code": "AuthFailure.SignatureFailure", "Message": "signature mismatch"}, "RequestId": "x"}}"#)
+            .create();
+
+        let api = TencentCloudApi::new(&account(&server.url()));
+        let err = api.describe(&["ins-1".to_string()]).unwrap_err();
+        assert_eq!(err.to_string(), "AuthFailure.SignatureFailure: signature mismatch");
+    }
+}
@@ -0,0 +1,265 @@
+use crate::alert::{format_time, Code};
+use crate::table;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// the default path of the history file
+pub const PATH: &str = "/var/lib/ic/history.jsonl";
+
+// an interruption of the spot instance detected by `SpotPatrol`.
+// the fields added later are optional, so the old records can still be loaded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    // when the interruption is detected
+    pub time: DateTime<Utc>,
+    pub code: Code,
+    #[serde(default)]
+    pub provider: Option<String>,
+    pub instance_id: String,
+    pub instance_type: Option<String>,
+    pub region: Option<String>,
+    #[serde(default)]
+    pub zone: Option<String>,
+    // when the instance was booted, the uptime starts from it
+    #[serde(default)]
+    pub boot: Option<DateTime<Utc>>,
+    // when the instance is going to be released
+    pub termination: Option<DateTime<Utc>>,
+    // the outcomes of the pre-termination hooks
+    #[serde(default)]
+    pub hooks: Vec<HookRecord>,
+}
+
+impl Record {
+    // from the boot to the termination, or to the detection if the termination time is unknown
+    pub fn uptime(&self) -> Option<TimeDelta> {
+        self.boot.map(|b| self.termination.unwrap_or(self.time) - b)
+    }
+}
+
+// the outcome of a hook, the error is None if it succeeded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HookRecord {
+    pub name: String,
+    pub elapsed: f64, // seconds
+    pub error: Option<String>,
+}
+
+// the interruptions of an instance type in a zone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stat {
+    pub instance_type: String,
+    pub zone: String,
+    pub interruptions: usize,
+    // the mean of the known uptimes, None if there is none
+    pub mean_uptime: Option<TimeDelta>,
+}
+
+// group the records by instance type and zone, the unknown ones are shown as "-"
+pub fn stats(records: &[Record]) -> Vec<Stat> {
+    let mut groups: BTreeMap<(String, String), Vec<&Record>> = BTreeMap::new();
+    for r in records {
+        let key = (
+            r.instance_type.clone().unwrap_or("-".to_string()),
+            r.zone.clone().unwrap_or("-".to_string()),
+        );
+        groups.entry(key).or_default().push(r);
+    }
+    groups
+        .into_iter()
+        .map(|((instance_type, zone), rs)| {
+            let uptimes: Vec<TimeDelta> = rs.iter().filter_map(|r| r.uptime()).collect();
+            let mean_uptime = match uptimes.len() {
+                0 => None,
+                n => Some(uptimes.iter().sum::<TimeDelta>() / n as i32),
+            };
+            Stat {
+                instance_type,
+                zone,
+                interruptions: rs.len(),
+                mean_uptime,
+            }
+        })
+        .collect()
+}
+
+// the records followed by the statistics, example:
+// TIME                 PROVIDER  INSTANCE  TYPE          REGION       ZONE           UPTIME  HOOKS
+// 2024-09-12 15:51:54  AliCloud  i-1       ecs.g7.large  cn-hangzhou  cn-hangzhou-i  26.0h   1/1
+//
+// TYPE          ZONE           INTERRUPTIONS  MEAN UPTIME
+// ecs.g7.large  cn-hangzhou-i  1              26.0h
+pub fn render(records: &[Record]) -> String {
+    let dash = |s: &Option<String>| s.clone().unwrap_or("-".to_string());
+    let mut rows = vec![["TIME", "PROVIDER", "INSTANCE", "TYPE", "REGION", "ZONE", "UPTIME", "HOOKS"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<String>>()];
+    for r in records {
+        let succeeded = r.hooks.iter().filter(|h| h.error.is_none()).count();
+        rows.push(vec![
+            format_time(&r.time),
+            dash(&r.provider),
+            r.instance_id.clone(),
+            dash(&r.instance_type),
+            dash(&r.region),
+            dash(&r.zone),
+            hours(r.uptime()),
+            format!("{succeeded}/{}", r.hooks.len()),
+        ]);
+    }
+    let mut summary = vec![["TYPE", "ZONE", "INTERRUPTIONS", "MEAN UPTIME"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<String>>()];
+    for s in stats(records) {
+        summary.push(vec![s.instance_type, s.zone, s.interruptions.to_string(), hours(s.mean_uptime)]);
+    }
+    format!("{}\n{}", table::render(&rows), table::render(&summary))
+}
+
+// example: 26.5h
+fn hours(d: Option<TimeDelta>) -> String {
+    match d {
+        Some(d) => format!("{:.1}h", d.num_seconds() as f64 / 3600.0),
+        None => "-".to_string(),
+    }
+}
+
+// History keeps the records in a file, one json object per line.
+// the file is only appended, so the records survive a crash
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: &Path) -> History {
+        History { path: path.to_path_buf() }
+    }
+
+    pub fn append(&self, record: &Record) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)
+    }
+
+    // all the records in order, the broken lines are skipped
+    pub fn load(&self) -> io::Result<Vec<Record>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        Ok(content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .filter_map(|l| {
+                serde_json::from_str(l)
+                    .map_err(|err| warn!("history - invalid record {l}: {err}"))
+                    .ok()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn record(id: &str) -> Record {
+        Record {
+            time: Utc.with_ymd_and_hms(2024, 9, 12, 7, 51, 54).unwrap(),
+            code: Code::AliCloudInterrupt,
+            provider: Some("AliCloud".to_string()),
+            instance_id: id.to_string(),
+            instance_type: Some("ecs.g7.large".to_string()),
+            region: Some("cn-hangzhou".to_string()),
+            zone: Some("cn-hangzhou-i".to_string()),
+            boot: None,
+            termination: None,
+            hooks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_append_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        // the parent directory is created as well
+        let history = History::new(&dir.path().join("ic/history.jsonl"));
+        assert_eq!(history.load().unwrap(), vec![]);
+
+        history.append(&record("i-1")).unwrap();
+        history.append(&record("i-2")).unwrap();
+        assert_eq!(history.load().unwrap(), vec![record("i-1"), record("i-2")]);
+    }
+
+    #[test]
+    fn test_skip_broken_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let line = serde_json::to_string(&record("i-1")).unwrap();
+        fs::write(&path, format!("{line}\n{{\"time\": \n\n")).unwrap();
+        assert_eq!(History::new(&path).load().unwrap(), vec![record("i-1")]);
+    }
+
+    #[test]
+    fn test_load_old_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let line = r#"{"time":"2024-09-12T07:51:54Z","code":"AliCloudInterrupt","instance_id":"i-1","instance_type":null,"region":null,"termination":null}"#;
+        fs::write(&path, format!("{line}\n")).unwrap();
+        let records = History::new(&path).load().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].zone, None);
+        assert!(records[0].hooks.is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut a = record("i-1");
+        a.boot = Some(a.time - TimeDelta::hours(10));
+        // the uptime ends at the termination time
+        let mut b = record("i-2");
+        b.boot = Some(b.time - TimeDelta::hours(20));
+        b.termination = Some(b.time + TimeDelta::hours(2));
+        // the boot time is unknown
+        let c = record("i-3");
+        let mut d = record("i-4");
+        d.instance_type = None;
+
+        let stats = stats(&[a, b, c, d]);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].instance_type, "-");
+        assert_eq!(stats[0].mean_uptime, None);
+        assert_eq!(stats[1].instance_type, "ecs.g7.large");
+        assert_eq!(stats[1].zone, "cn-hangzhou-i");
+        assert_eq!(stats[1].interruptions, 3);
+        assert_eq!(stats[1].mean_uptime, Some(TimeDelta::hours(16)));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut r = record("i-1");
+        r.boot = Some(r.time - TimeDelta::hours(26));
+        r.hooks = vec![
+            HookRecord { name: "docker stop web".to_string(), elapsed: 2.1, error: None },
+            HookRecord { name: "sync".to_string(), elapsed: 10.0, error: Some("timeout".to_string()) },
+        ];
+        assert_eq!(
+            render(&[r]),
+            "TIME                 PROVIDER  INSTANCE  TYPE          REGION       ZONE           UPTIME  HOOKS\n\
+             2024-09-12 15:51:54  AliCloud  i-1       ecs.g7.large  cn-hangzhou  cn-hangzhou-i  26.0h   1/2\n\
+             \n\
+             TYPE          ZONE           INTERRUPTIONS  MEAN UPTIME\n\
+             ecs.g7.large  cn-hangzhou-i  1              26.0h\n"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use super::{tls, Packet};
+use crate::config::{KnownClient, Unknown};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(key: &str, p: &Packet) -> HmacSha256 {
+    // hmac accepts a key of any length
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("hmac key");
+    // one field per line, so that the fields can't be shifted into each other
+    let mut payload = format!("{}\n{}\n{}\n{}", p.name, p.msg, p.timestamp, p.nonce);
+    // the advertised timing is appended only if any, so the packets of the old clients are still verified
+    if p.interval.is_some() || p.grace.is_some() {
+        let opt = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+        payload.push_str(&format!("\n{}\n{}", opt(p.interval), opt(p.grace.map(u64::from))));
+    }
+    // the labels are sorted key=value lines, so that their order doesn't matter.
+    // both sides are quoted as json strings, so that the separators in a value can't forge another label
+    if !p.labels.is_empty() {
+        let quote = |s: &str| serde_json::to_string(s).expect("json string");
+        let mut labels: Vec<String> = p.labels.iter().map(|(k, v)| format!("{}={}", quote(k), quote(v))).collect();
+        labels.sort();
+        payload.push_str(&format!("\nlabels\n{}", labels.join("\n")));
+    }
+    if let Some(bye) = &p.goodbye {
+        let back_at = bye.back_at.map(|t| t.timestamp().to_string()).unwrap_or_default();
+        payload.push_str(&format!("\ngoodbye\n{}\n{back_at}", bye.reason.as_deref().unwrap_or("")));
+    }
+    mac.update(payload.as_bytes());
+    mac
+}
+
+// the signature of a packet in base64, the key itself is never sent
+pub fn sign(key: &str, p: &Packet) -> String {
+    STANDARD.encode(mac(key, p).finalize().into_bytes())
+}
+
+// 16 random bytes in hex
+pub fn nonce() -> String {
+    let mut buf = [0u8; 16];
+    getrandom::getrandom(&mut buf).expect("random nonce");
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// how a verified heartbeat is accepted
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Verdict {
+    // by the credentials of a listed client
+    Listed,
+    // by the shared key
+    Accepted,
+    // by the shared key, and the client should be alerted when it is seen for the first time
+    Alerted,
+}
+
+// Auth verifies the signed heartbeats, it is shared by all the connections of a server.
+// a heartbeat is accepted only once, the nonces are remembered as long as their timestamps are valid
+#[derive(Debug)]
+pub struct Auth {
+    key: String, // shared by the clients which aren't listed
+    skew: i64, // seconds
+    clients: HashMap<String, KnownClient>,
+    unknown: Unknown,
+    nonces: Mutex<HashMap<String, i64>>,
+}
+
+impl Auth {
+    pub fn new(key: &str, skew: u64) -> Auth {
+        Auth {
+            key: key.to_string(),
+            skew: skew as i64,
+            clients: HashMap::new(),
+            unknown: Unknown::Accept,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // every client needs its own key or certificate fingerprint, and the names must be unique
+    pub fn with_clients(mut self, clients: &[KnownClient], unknown: Unknown) -> Result<Auth, String> {
+        for c in clients {
+            if c.key.is_none() && c.fingerprint.is_none() {
+                return Err(format!("the client {} requires a key or a fingerprint", c.name));
+            }
+            let mut c = c.clone();
+            c.fingerprint = c.fingerprint.as_deref().map(tls::parse_fingerprint).transpose()?;
+            if let Some(c) = self.clients.insert(c.name.clone(), c) {
+                return Err(format!("the client {} is duplicated", c.name));
+            }
+        }
+        self.unknown = unknown;
+        Ok(self)
+    }
+
+    // the listed client of the identity
+    pub fn client(&self, identity: &str) -> Option<&KnownClient> {
+        self.clients.get(identity)
+    }
+
+    // check the timestamp, the credentials and the nonce in order.
+    // the identity is the name in the packet, or the common name of the certificate with mutual tls,
+    // whose fingerprint is also passed in.
+    // the nonce is cached only after the signature is verified, so that the cache can't be flooded
+    pub fn verify(&self, p: &Packet, identity: &str, fingerprint: Option<&str>, now: i64) -> Result<Verdict, String> {
+        if (now - p.timestamp).abs() > self.skew {
+            return Err(format!("the timestamp {} is out of the window", p.timestamp));
+        }
+        let (key, verdict) = match self.clients.get(identity) {
+            Some(c) if c.revoked => return Err(format!("the client {identity} is revoked")),
+            Some(c) => {
+                if c.fingerprint.is_some() && c.fingerprint.as_deref() != fingerprint {
+                    return Err(format!("the certificate of {identity} mismatches"));
+                }
+                // a client may be authenticated by its certificate only
+                (c.key.as_deref(), Verdict::Listed)
+            }
+            None => match self.unknown {
+                Unknown::Accept => (Some(self.key.as_str()), Verdict::Accepted),
+                Unknown::Alert => (Some(self.key.as_str()), Verdict::Alerted),
+                Unknown::Reject => return Err(format!("the client {identity} is unknown")),
+            },
+        };
+        if let Some(key) = key {
+            let signature = STANDARD.decode(&p.signature).map_err(|_| "invalid signature".to_string())?;
+            // compared in constant time
+            mac(key, p)
+                .verify_slice(&signature)
+                .map_err(|_| "invalid signature".to_string())?;
+        }
+
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.retain(|_, t| (now - *t).abs() <= self.skew);
+        if nonces.insert(p.nonce.clone(), p.timestamp).is_some() {
+            return Err(format!("the nonce {} is replayed", p.nonce));
+        }
+        Ok(verdict)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keepalive::Goodbye;
+
+    fn packet(key: &str, msg: &str, timestamp: i64, nonce: &str) -> Packet {
+        named("Q", key, msg, timestamp, nonce)
+    }
+
+    fn named(name: &str, key: &str, msg: &str, timestamp: i64, nonce: &str) -> Packet {
+        let mut p = Packet {
+            name: name.to_string(),
+            msg: msg.to_string(),
+            labels: vec![],
+            timestamp,
+            nonce: nonce.to_string(),
+            interval: None,
+            grace: None,
+            goodbye: None,
+            signature: String::new(),
+        };
+        p.signature = sign(key, &p);
+        p
+    }
+
+    impl Auth {
+        fn verify_packet(&self, p: &Packet, now: i64) -> Result<Verdict, String> {
+            self.verify(p, &p.name.clone(), None, now)
+        }
+    }
+
+    fn client(name: &str, key: Option<&str>, fingerprint: Option<&str>) -> KnownClient {
+        KnownClient {
+            name: name.to_string(),
+            key: key.map(String::from),
+            fingerprint: fingerprint.map(String::from),
+            display_name: None,
+            labels: Default::default(),
+            revoked: false,
+            timeout: None,
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let p = named("Q", "hello", "I am active", 1726063290, "00112233445566778899aabbccddeeff");
+        assert_eq!(p.signature, "+YX9gHvIVyV78JT2ept7D/KQntG21FmcyGRdOhvVbfA=");
+        let (a, b) = (nonce(), nonce());
+        assert_eq!(a.len(), 32);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_verify() {
+        let auth = Auth::new("hello", 300);
+        let now = 1726063290;
+        assert_eq!(auth.verify_packet(&packet("hello", "hi", now - 10, "n1"), now), Ok(Verdict::Accepted));
+        // replayed
+        assert!(auth.verify_packet(&packet("hello", "hi", now - 10, "n1"), now).unwrap_err().contains("replayed"));
+        // out of the window
+        assert!(auth.verify_packet(&packet("hello", "hi", now - 301, "n2"), now).unwrap_err().contains("window"));
+        assert!(auth.verify_packet(&packet("hello", "hi", now + 301, "n2"), now).unwrap_err().contains("window"));
+        // the wrong key, or the message is tampered
+        assert_eq!(auth.verify_packet(&packet("world", "hi", now, "n3"), now), Err("invalid signature".to_string()));
+        let mut p = packet("hello", "hi", now, "n4");
+        p.msg = "bye".to_string();
+        assert_eq!(auth.verify_packet(&p, now), Err("invalid signature".to_string()));
+        // the advertised timing is signed as well
+        let mut p = packet("hello", "hi", now, "n5");
+        p.interval = Some(300);
+        assert_eq!(auth.verify_packet(&p, now), Err("invalid signature".to_string()));
+        p.signature = sign("hello", &p);
+        p.grace = Some(2);
+        assert_eq!(auth.verify_packet(&p, now), Err("invalid signature".to_string()));
+        p.signature = sign("hello", &p);
+        assert_eq!(auth.verify_packet(&p, now), Ok(Verdict::Accepted));
+        // and so are the labels, in any order
+        let label = |k: &str, v: &str| (k.to_string(), v.to_string());
+        let mut p = packet("hello", "hi", now, "n7");
+        p.labels = vec![label("instance_id", "i-1"), label("public_ip", "1.2.3.4")];
+        p.signature = sign("hello", &p);
+        p.labels[1].1 = "5.6.7.8".to_string();
+        assert_eq!(auth.verify_packet(&p, now), Err("invalid signature".to_string()));
+        p.labels.pop();
+        assert_eq!(auth.verify_packet(&p, now), Err("invalid signature".to_string()));
+        p.labels = vec![label("public_ip", "1.2.3.4"), label("instance_id", "i-1")];
+        assert_eq!(auth.verify_packet(&p, now), Ok(Verdict::Accepted));
+        // the separators in a value can't forge another label
+        let mut p = packet("hello", "hi", now, "n8");
+        p.labels = vec![label("a", "1\"=\"b"), label("c", "2")];
+        p.signature = sign("hello", &p);
+        p.labels = vec![label("a", "1"), label("b", "c"), label("c", "2")];
+        assert_eq!(auth.verify_packet(&p, now), Err("invalid signature".to_string()));
+        // and so is the goodbye
+        let mut p = packet("hello", "hi", now, "n6");
+        p.goodbye = Some(Goodbye { reason: Some("reboot".to_string()), back_at: None });
+        assert_eq!(auth.verify_packet(&p, now), Err("invalid signature".to_string()));
+        // the invalid attempts don't occupy the nonces
+        assert_eq!(auth.verify_packet(&packet("hello", "hi", now, "n3"), now), Ok(Verdict::Accepted));
+    }
+
+    #[test]
+    fn test_expired_nonces() {
+        let auth = Auth::new("hello", 300);
+        let now = 1726063290;
+        auth.verify_packet(&packet("hello", "hi", now, "n1"), now).unwrap();
+        auth.verify_packet(&packet("hello", "hi", now + 200, "n2"), now + 200).unwrap();
+        // n1 is forgotten once it is out of the window
+        auth.verify_packet(&packet("hello", "hi", now + 400, "n3"), now + 400).unwrap();
+        assert_eq!(auth.nonces.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_clients() {
+        let fingerprint = "AB".repeat(32);
+        let clients = vec![
+            client("a", Some("ka"), None),
+            client("b", Some("kb"), None),
+            client("c", None, Some(&fingerprint)),
+            KnownClient { revoked: true, ..client("d", Some("kd"), None) },
+        ];
+        let auth = Auth::new("shared", 300).with_clients(&clients, Unknown::Reject).unwrap();
+        let now = 1726063290;
+        // every client has its own key
+        assert_eq!(auth.verify_packet(&named("a", "ka", "hi", now, "n1"), now), Ok(Verdict::Listed));
+        assert_eq!(auth.verify_packet(&named("b", "ka", "hi", now, "n2"), now), Err("invalid signature".to_string()));
+        assert_eq!(auth.verify_packet(&named("b", "kb", "hi", now, "n2"), now), Ok(Verdict::Listed));
+        // the revoked one is rejected, the others are not affected
+        assert!(auth.verify_packet(&named("d", "kd", "hi", now, "n3"), now).unwrap_err().contains("revoked"));
+        assert_eq!(auth.verify_packet(&named("a", "ka", "hi", now, "n4"), now), Ok(Verdict::Listed));
+        // the unknown one is rejected even with the shared key
+        assert!(auth.verify_packet(&named("e", "shared", "hi", now, "n5"), now).unwrap_err().contains("unknown"));
+        // authenticated by the certificate
+        let p = named("c", "", "hi", now, "n6");
+        assert!(auth.verify(&p, "c", None, now).unwrap_err().contains("mismatches"));
+        assert_eq!(auth.verify(&p, "c", Some(&"ab".repeat(32)), now), Ok(Verdict::Listed));
+
+        let auth = Auth::new("shared", 300).with_clients(&clients, Unknown::Alert).unwrap();
+        assert_eq!(auth.verify_packet(&named("e", "shared", "hi", now, "n1"), now), Ok(Verdict::Alerted));
+        assert_eq!(auth.verify_packet(&named("f", "ka", "hi", now, "n2"), now), Err("invalid signature".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_clients() {
+        let err = Auth::new("", 300).with_clients(&[client("a", None, None)], Unknown::Reject).unwrap_err();
+        assert_eq!(err, "the client a requires a key or a fingerprint");
+        let clients = [client("a", Some("k1"), None), client("a", Some("k2"), None)];
+        let err = Auth::new("", 300).with_clients(&clients, Unknown::Reject).unwrap_err();
+        assert_eq!(err, "the client a is duplicated");
+        let err = Auth::new("", 300).with_clients(&[client("a", None, Some("ab"))], Unknown::Reject).unwrap_err();
+        assert_eq!(err, "invalid sha256 fingerprint: ab");
+    }
+}
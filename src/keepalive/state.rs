@@ -0,0 +1,95 @@
+use super::WhiteList;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// the clients seen by the server when it is saved
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub saved_at: Option<DateTime<Utc>>,
+    pub peers: WhiteList,
+    // the last time the server was alive, which is kept in another file, see `State::touch`
+    #[serde(skip)]
+    pub alive_at: Option<DateTime<Utc>>,
+}
+
+// State keeps the whitelist in a json file, so that a restarted server knows which clients are offline.
+// the file is replaced as a whole, a crash while writing leaves the previous one
+#[derive(Debug, Clone)]
+pub struct State {
+    path: PathBuf,
+}
+
+impl State {
+    pub fn new(path: &Path) -> State {
+        State { path: path.to_path_buf() }
+    }
+
+    pub fn save(&self, peers: &WhiteList, now: DateTime<Utc>) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let snapshot = Snapshot { saved_at: Some(now), peers: peers.clone(), alive_at: None };
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&snapshot)?)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    // the server is alive at the time. the clients are saved only if they change,
+    // so a quiet server refreshes this tiny file instead, then its downtime is known after a restart
+    pub fn touch(&self, now: DateTime<Utc>) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(self.alive_path(), now.to_rfc3339())
+    }
+
+    // an empty snapshot if the file doesn't exist
+    pub fn load(&self) -> io::Result<Snapshot> {
+        let mut snapshot: Snapshot = match fs::read_to_string(&self.path) {
+            Ok(c) => serde_json::from_str(&c)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Snapshot::default(),
+            Err(err) => return Err(err),
+        };
+        // a missing or broken one is ignored, then the time of the last save is used
+        snapshot.alive_at = fs::read_to_string(self.alive_path())
+            .ok()
+            .and_then(|t| DateTime::parse_from_rfc3339(t.trim()).ok())
+            .map(|t| t.with_timezone(&Utc));
+        Ok(snapshot)
+    }
+
+    fn alive_path(&self) -> PathBuf {
+        self.path.with_extension("alive")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keepalive::Peer;
+    use chrono::{TimeDelta, TimeZone};
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = State::new(&dir.path().join("ic/keepalive.json"));
+        assert_eq!(state.load().unwrap(), Snapshot::default());
+
+        let now = Utc.with_ymd_and_hms(2024, 9, 12, 7, 51, 54).unwrap();
+        let peers = WhiteList::from([
+            ("a".to_string(), Peer { deadline: now, last_seen: Some(now), ..Default::default() }),
+            ("b".to_string(), Peer { offline: true, offline_since: Some(now), ..Default::default() }),
+        ]);
+        state.save(&peers, now).unwrap();
+        assert_eq!(state.load().unwrap(), Snapshot { saved_at: Some(now), peers, alive_at: None });
+        let later = now + TimeDelta::hours(3);
+        state.touch(later).unwrap();
+        assert_eq!(state.load().unwrap().alive_at, Some(later));
+
+        fs::write(dir.path().join("ic/keepalive.json"), "{").unwrap();
+        assert!(state.load().is_err());
+    }
+}
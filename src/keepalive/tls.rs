@@ -0,0 +1,232 @@
+use crate::config;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+// only the ring provider is compiled in
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+// the certificates in a pem file, at least one is required
+fn certs(path: &str) -> Result<Vec<CertificateDer<'static>>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path).map_err(|err| format!("{path}: {err}"))?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(Box::from(format!("{path}: no certificate")));
+    }
+    Ok(certs)
+}
+
+fn private_key(path: &str) -> Result<PrivateKeyDer<'static>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path).map_err(|err| format!("{path}: {err}"))?);
+    rustls_pemfile::private_key(&mut reader)?.ok_or(Box::from(format!("{path}: no private key")))
+}
+
+fn roots(path: &str) -> Result<RootCertStore, Box<dyn Error>> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+pub fn server_config(conf: &config::ServerTls) -> Result<Arc<ServerConfig>, Box<dyn Error>> {
+    let builder = ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+    let builder = match &conf.client_ca {
+        Some(ca) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots(ca)?), provider()).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(builder.with_single_cert(certs(&conf.cert)?, private_key(&conf.key)?)?))
+}
+
+pub fn client_config(conf: &config::ClientTls) -> Result<Arc<ClientConfig>, Box<dyn Error>> {
+    let builder = ClientConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+    let builder = match (&conf.fingerprint, &conf.ca) {
+        (Some(f), _) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Pinned::new(f)?)),
+        (None, Some(ca)) => builder.with_root_certificates(roots(ca)?),
+        (None, None) => return Err(Box::from("either a ca or a fingerprint is required")),
+    };
+    let config = match (&conf.cert, &conf.key) {
+        (Some(cert), Some(key)) => builder.with_client_auth_cert(certs(cert)?, private_key(key)?)?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err(Box::from("both the cert and the key are required for mutual tls")),
+    };
+    Ok(Arc::new(config))
+}
+
+// the sha256 fingerprint in lowercase hex, example: 9734...67c1
+pub fn fingerprint(cert: &[u8]) -> String {
+    Sha256::digest(cert).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// the colons and the case are ignored, as `openssl x509 -fingerprint -sha256` prints them
+pub fn parse_fingerprint(fingerprint: &str) -> Result<String, String> {
+    let f = fingerprint.replace(':', "").to_lowercase();
+    if f.len() != 64 || !f.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid sha256 fingerprint: {fingerprint}"));
+    }
+    Ok(f)
+}
+
+// Pinned trusts the certificate with the fingerprint only, regardless of its issuer and names,
+// so a self-signed certificate works without a ca. the handshake signatures are still verified
+#[derive(Debug)]
+struct Pinned {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl Pinned {
+    fn new(fingerprint: &str) -> Result<Pinned, String> {
+        Ok(Pinned { fingerprint: parse_fingerprint(fingerprint)?, provider: provider() })
+    }
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match fingerprint(end_entity) == self.fingerprint {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(rustls::Error::General("the fingerprint of the certificate mismatches".to_string())),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+// split a DER element into (tag, content, the rest)
+fn der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let len = match first {
+        n if n < 0x80 => n as usize,
+        n => {
+            // the long form, the length takes n & 0x7f bytes
+            let n = (n & 0x7f) as usize;
+            if n == 0 || n > 4 || rest.len() < n {
+                return None;
+            }
+            let len = rest[..n].iter().fold(0, |acc, b| acc << 8 | *b as usize);
+            rest = &rest[n..];
+            len
+        }
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+// the common name (CN) in the subject of a DER certificate.
+// the certificate has been verified by rustls, so only the path to the subject is walked:
+// Certificate -> TBSCertificate -> [version], serial, signature, issuer, validity, subject
+pub fn common_name(cert: &[u8]) -> Option<String> {
+    const CN: &[u8] = &[0x55, 0x04, 0x03]; // 2.5.4.3
+    let (_, cert, _) = der(cert)?;
+    let (_, mut tbs, _) = der(cert)?;
+    if tbs.first() == Some(&0xa0) {
+        tbs = der(tbs)?.2;
+    }
+    for _ in 0..4 {
+        tbs = der(tbs)?.2;
+    }
+    // Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value ANY }
+    let (_, mut rdns, _) = der(tbs)?;
+    while !rdns.is_empty() {
+        let (_, mut set, rest) = der(rdns)?;
+        rdns = rest;
+        while !set.is_empty() {
+            let (_, attr, rest) = der(set)?;
+            set = rest;
+            let (_, oid, value) = der(attr)?;
+            if oid == CN {
+                let (_, value, _) = der(value)?;
+                return String::from_utf8(value.to_vec()).ok();
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn testdata(name: &str) -> String {
+        format!("{}/testdata/tls/{name}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn test_common_name() {
+        let cert = certs(&testdata("client.pem")).unwrap().remove(0);
+        assert_eq!(common_name(&cert), Some("home-lab-1".to_string()));
+        let cert = certs(&testdata("server.pem")).unwrap().remove(0);
+        assert_eq!(common_name(&cert), Some("localhost".to_string()));
+        assert_eq!(common_name(&[0x30, 0x03, 0x02]), None);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let cert = certs(&testdata("server.pem")).unwrap().remove(0);
+        let pinned = Pinned::new("97:34:56:43:8C:78:5C:04:4B:2A:BC:6D:5A:14:44:13:D5:34:5D:3A:F5:48:0C:4D:E5:46:7A:79:6D:E0:67:C1").unwrap();
+        assert_eq!(fingerprint(&cert), pinned.fingerprint);
+        assert!(Pinned::new("97:34").is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        let conf = config::ServerTls {
+            cert: testdata("server.pem"),
+            key: testdata("server.key"),
+            client_ca: Some(testdata("ca.pem")),
+        };
+        assert!(server_config(&conf).is_ok());
+        let conf = config::ServerTls { cert: testdata("missing.pem"), ..conf };
+        assert!(server_config(&conf).unwrap_err().to_string().contains("missing.pem"));
+
+        let conf = config::ClientTls { ca: Some(testdata("ca.pem")), ..Default::default() };
+        assert!(client_config(&conf).is_ok());
+        let conf = config::ClientTls { cert: Some(testdata("client.pem")), ..conf };
+        assert!(client_config(&conf).is_err());
+        assert!(client_config(&config::ClientTls::default()).is_err());
+    }
+}
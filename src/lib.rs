@@ -0,0 +1,58 @@
+/*!
+Interrupt-Callback monitors spot instances and local servers, and triggers
+alerts when something goes wrong.
+
+The `ic` binary is a thin wrapper around this library, so the same building
+blocks can be embedded into other Rust services:
+
+- [`spot`]: query the interruption status of spot instances ([`Spot`], [`SpotPatrol`])
+- [`controller`]: watch spot instances from the outside through cloud apis ([`Controller`])
+- [`keepalive`]: heartbeat over the `ic://` protocol ([`TcpClient`], [`TcpServer`])
+- [`advisor`]: rank spot instance types by price and interruptions, used by `ic spot advise`
+- [`alert`]: notify integrations such as Feishu ([`Alert`], [`Notice`], [`Msg`])
+- [`config`]: the TOML configuration used by `ic`
+
+# Embedding a heartbeat client
+
+A service can report its liveness to an existing `ic` server by pinging it
+periodically in a background thread:
+
+```
+use interrupt_callback::alert::{Alert, AlertMap};
+use interrupt_callback::config;
+use interrupt_callback::{TcpClient, TcpServer};
+use std::sync::Arc;
+use std::thread;
+
+// an ic server, usually running on another machine
+let server = TcpServer::new(0, "watcher", 30, config::Server {
+    key: "hello".to_string(),
+    num: 4,
+    ..Default::default()
+})?;
+let addr = server.local_addr()?;
+thread::spawn(move || server.run(Arc::new(Alert::new(AlertMap::new()))));
+
+// the heartbeat client embedded in our own service
+let client = TcpClient::new(&format!("ic://default:hello@{addr}"), "my-service")?;
+let pong = client.ping("I am active")?;
+assert_eq!(pong.name, "watcher");
+# Ok::<(), Box<dyn std::error::Error>>(())
+```
+ */
+// the code moved from the binary keeps its original style
+#![allow(clippy::derivable_impls, clippy::to_string_in_format_args, clippy::unwrap_or_default)]
+
+pub mod advisor;
+pub mod alert;
+pub mod config;
+pub mod controller;
+pub mod history;
+pub mod keepalive;
+pub mod spot;
+mod table;
+
+pub use alert::{Alert, Msg, Notice};
+pub use controller::{CloudApi, Controller};
+pub use keepalive::{TcpClient, TcpServer};
+pub use spot::{Spot, SpotPatrol, SpotProvider, Status};
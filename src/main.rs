@@ -1,29 +1,34 @@
-mod config;
-mod alert;
-mod spot;
-mod keepalive;
-
-use crate::alert::Target::Myself;
-use crate::alert::{Alert, AlertMap, Code, Msg};
-use crate::keepalive::{TcpClient, TcpServer};
-use config::Provider;
 use env_logger::Builder;
+use chrono::{TimeDelta, Utc};
+use interrupt_callback::advisor;
+use interrupt_callback::alert::{self, AlertMap};
+use interrupt_callback::config::{self, Config};
+use interrupt_callback::controller;
+use interrupt_callback::history::{self, History};
+use interrupt_callback::spot::{self, Metadata, Pipeline};
+use interrupt_callback::{Alert, Controller, SpotPatrol, TcpClient, TcpServer};
 use log::{debug, error, info, warn, LevelFilter};
-use spot::Spot;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 use std::path::Path;
-use std::sync::Arc;
-use std::{env, thread, time::Duration};
+use std::sync::{mpsc, Arc};
+use std::{env, fs, process, thread, time::Duration};
 
 
 /*
  The main function will launch the following service base on config
  - check the status of the spot instance regularly
- - a tcp client with timed heartbeat
+ - check the status of other spot instances through the cloud apis
+ - a tcp client with timed heartbeat, which says goodbye on SIGTERM/SIGINT
  - launch a tcp server that monitor the status of multiple clients (default :9080)
 
  all the environment variables
  - CONFIG_PATH: path to the configuration file. optional, default is empty
  - SERVER_PORT: the port of tcp server. default is 9080
+
+ the subcommands
+ - ic spot advise: rank the spot instance types by price and interruptions
+ - ic history [--json]: show the interruption history, or export it as json
  */
 fn main() {
     Builder::new().filter_level(LevelFilter::Info).init();
@@ -31,6 +36,56 @@ fn main() {
     let conf_path = env::var("CONFIG_PATH").unwrap_or("/etc/ic/config.toml".to_string());
     let conf = config::load_config(Path::new(&conf_path)).unwrap();
     debug!("the config: {:?}", conf);
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(|a| a.as_str()).collect::<Vec<&str>>().as_slice() {
+        [] => run(conf),
+        ["spot", "advise"] => advise(&conf),
+        ["history"] => show_history(&conf, false),
+        ["history", "--json"] => show_history(&conf, true),
+        _ => {
+            eprintln!("usage: ic [spot advise | history [--json]]");
+            std::process::exit(2);
+        }
+    }
+}
+
+// the spot prices in the last 30 days of the instance types configured in the accounts
+fn advise(conf: &Config) {
+    let since = Utc::now() - TimeDelta::days(30);
+    let mut prices = vec![];
+    for account in conf.controller.accounts.iter().filter(|a| !a.instance_types.is_empty()) {
+        let Some(api) = controller::api(account) else {
+            continue;
+        };
+        match api.spot_prices(&account.instance_types, since) {
+            Ok(ps) => prices.extend(ps.into_iter().map(|p| (account.region.clone(), p))),
+            Err(err) => error!("advise - failed to query the prices of {}: {err}", account.region),
+        }
+    }
+    let records = History::new(Path::new(&conf.spot.history)).load().unwrap_or_else(|err| {
+        error!("advise - failed to load the history: {err}");
+        vec![]
+    });
+    print!("{}", advisor::render(&advisor::advise(&prices, &records, since)));
+}
+
+// the interruptions recorded by the spot patrol, with the mean uptime per instance type and zone
+fn show_history(conf: &Config, json: bool) {
+    let records = match History::new(Path::new(&conf.spot.history)).load() {
+        Ok(records) => records,
+        Err(err) => {
+            error!("history - failed to load {}: {err}", conf.spot.history);
+            std::process::exit(1);
+        }
+    };
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&records).unwrap()),
+        false => print!("{}", history::render(&records)),
+    }
+}
+
+fn run(conf: Config) {
     // 2. create an alert for notification
     let mut map = AlertMap::new();
     if let Some(fe) = conf.alert.feishu {
@@ -45,31 +100,57 @@ fn main() {
     let mut handles = vec![];
 
     // 3. monitor the status of the server
-    let spot = Spot::new();
-    let sp = SpotPatrol::new(conf.interval as u64, name.clone(), Arc::clone(&alert));
-    match conf.provider {
-        Provider::AliCloud => {
-            info!("create a thread used to monitor aliyun ecs");
-            let h = thread::spawn(move || {
-                sp.patrol(|| spot.query_ecs(), Code::AliCloudInterrupt);
-            });
-            handles.push(h);
+    let provider = spot::provider(&conf.provider, &conf.spot);
+    // the metadata of the instance is attached to all the alerts about it
+    let labels = match provider.as_ref() {
+        Some(p) => Metadata::fetch(p.as_ref()).labels(),
+        None => vec![],
+    };
+    info!("the labels are {labels:?}");
+    if let Some(provider) = provider {
+        info!("create a thread used to monitor the spot instance of {:?}", conf.provider);
+        let mut sp = SpotPatrol::new(conf.interval as u64, name.clone(), Arc::clone(&alert))
+            .with_hooks(Pipeline::new(conf.spot.on_interrupt))
+            .with_degraded_after(conf.spot.degraded_after)
+            .with_history(History::new(Path::new(&conf.spot.history)))
+            .with_labels(labels.clone());
+        if let Some(recovery) = conf.spot.recover.as_ref().and_then(controller::recovery) {
+            sp = sp.with_recovery(recovery);
         }
-        Provider::TencentCloud => {
-            info!("create a thread used to monitor tencentcloud cvm");
-            let h = thread::spawn(move || {
-                sp.patrol(|| spot.query_cvm(), Code::TencentCloudInterrupt)
-            });
-            handles.push(h);
+        let h = thread::spawn(move || sp.patrol(provider.as_ref()));
+        handles.push(h);
+    }
+
+    // 4. if configured, watch the instances from the outside
+    let mut ctrl = Controller::new(conf.controller.interval as u64, Arc::clone(&alert));
+    for account in conf.controller.accounts.iter() {
+        if let Some(api) = controller::api(account) {
+            ctrl = ctrl.watch(api, account.instances.clone(), controller::recovery(account));
         }
-        _ => (),
-    };
+    }
+    if !ctrl.is_empty() {
+        let h = thread::spawn(move || ctrl.patrol());
+        handles.push(h);
+        info!("start a controller");
+    }
 
-    // 4. if configured, turn on a client with timed heartbeat
+    // 5. if configured, turn on a client with timed heartbeat
     let period = conf.keepalive.period;
     if let Some(c) = conf.keepalive.client {
-        match TcpClient::new(&c.uri, &name.clone()) {
+        let client = TcpClient::new(&c.uri, &name.clone())
+            .map(|client| client.with_labels(labels).with_interval(period as u64))
+            .map(|client| match c.grace {
+                Some(g) => client.with_grace(g),
+                None => client,
+            });
+        let client = match c.tls.as_ref() {
+            Some(tls) => client.and_then(|c| c.with_tls(tls)),
+            None => client,
+        };
+        match client {
             Ok(client) => {
+                let client = Arc::new(client);
+                goodbye_on_signals(Arc::clone(&client), c.goodbye.clone());
                 let h = thread::spawn(move || {
                     // super loop
                     loop {
@@ -86,7 +167,7 @@ fn main() {
             Err(err) => error!("failed to create a client: {err}")
         };
     }
-    // 5. if configured, launch a server
+    // 6. if configured, launch a server
     if let Some(s) = conf.keepalive.server {
         let port: u16 = match env::var("SERVER_PORT") {
             Ok(s) => s.parse::<u16>().unwrap_or(9080),
@@ -109,53 +190,46 @@ fn main() {
     warn!("please check the configuration file, it is all over");
 }
 
+// the longest wait for the goodbye before exiting, the client gives up by its own timeouts earlier
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(15);
 
-struct SpotPatrol {
-    interval: u64, // patrol interval
-    name: String,
-    alert: Arc<Alert>,
-}
-
-impl SpotPatrol {
-    fn new(interval: u64, name: String, alert: Arc<Alert>) -> SpotPatrol {
-        SpotPatrol {
-            interval,
-            name,
-            alert,
+// tell the server that the client is stopped deliberately, then exit.
+// the reason and the expected return time are read from the note written before stopping, if any
+fn goodbye_on_signals(client: Arc<TcpClient>, note: String) {
+    let mut signals = match Signals::new([SIGTERM, SIGINT]) {
+        Ok(s) => s,
+        Err(err) => {
+            error!("failed to register the signals: {err}");
+            return;
         }
-    }
-
-    fn patrol<F>(&self, query: F, code: Code)
-    where
-        F: Fn() -> Result<i8, reqwest::Error>,
-    {
-        // super loop
-        loop {
-            match query() {
-                Ok(i) => {
-                    match i {
-                        // will be released in a few minutes
-                        0 => {
-                            // clone a copy
-                            let alert = Arc::clone(&self.alert);
-                            let name = self.name.clone();
-                            let code = code.clone();
-                            thread::spawn(move || alert.send(&Msg::new(code, Myself(name))));
-                            // end the thread
-                            break;
-                        }
-                        1 => info!("everything is ok with this server"),
-                        u => error!("unknown error: {u}"),
-                    };
-                }
-                Err(err) => {
-                    error!("spot - query error: {}", err);
+    };
+    thread::spawn(move || {
+        if let Some(sig) = signals.forever().next() {
+            info!("client - received the signal {sig}, say goodbye");
+            let path = Path::new(&note);
+            let bye = config::Goodbye::load(path).unwrap_or_else(|err| {
+                warn!("client - invalid goodbye note {note}: {err}");
+                config::Goodbye::default()
+            });
+            let reason = bye.reason.unwrap_or(format!("signal {sig}"));
+            let back_at = bye.back_in.and_then(|s| {
+                let back_in = i64::try_from(s).ok().and_then(TimeDelta::try_seconds)?;
+                Utc::now().checked_add_signed(back_in)
+            });
+            // the process exits anyway, even if the server is unresponsive
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || {
+                let _ = tx.send(client.goodbye(Some(&reason), back_at).map_err(|err| err.to_string()));
+            });
+            match rx.recv_timeout(GOODBYE_TIMEOUT) {
+                // the note is only for this shutdown
+                Ok(Ok(_)) => {
+                    let _ = fs::remove_file(path);
                 }
-            };
-            // delay
-            thread::sleep(Duration::from_secs(self.interval));
+                Ok(Err(err)) => error!("client - goodbye error: {err}"),
+                Err(_) => error!("client - goodbye timed out"),
+            }
+            process::exit(0);
         }
-    }
+    });
 }
-
-
@@ -1,7 +1,38 @@
-use log::error;
-use reqwest::{blocking::Client, Error, StatusCode};
+use crate::alert::Target::Myself;
+use crate::alert::{Alert, Code, Labels, Msg};
+use crate::config;
+use crate::controller::{Launch, Recovery};
+use crate::history::{History, HookRecord, Record};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{error, info, warn};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{Error, Method, StatusCode};
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
+use sysinfo::System;
 
+mod alicloud;
+mod aws;
+mod azure;
+mod detect;
+mod gcp;
+mod hook;
+mod huaweicloud;
+mod tencentcloud;
+mod volcengine;
+
+pub use alicloud::AliCloud;
+pub use aws::AwsEc2;
+pub use azure::Azure;
+pub use detect::{detect, Detector, Probe};
+pub use gcp::Gcp;
+pub use hook::{Outcome, Pipeline};
+pub use huaweicloud::HuaweiCloud;
+pub use tencentcloud::TencentCloud;
+pub use volcengine::Volcengine;
+
+// Spot is a tiny http client shared by all providers to access the metadata service
 pub struct Spot {
     client: Client,
 }
@@ -13,88 +44,880 @@ impl Spot {
         }
     }
 
-    // there are the following results
-    // 0: it means that the instance will be released in a few minutes.
-    // 1: normal
-    // 2. unknown error
-    fn query(&self, url: String) -> Result<i8, Error> {
-        // limit 1s
-        let res = self
-            .client
-            .get(url)
-            .timeout(Duration::from_secs(1))
-            .send()?;
+    // send a request to the metadata service with extra headers, limit 1s
+    pub fn send(&self, method: Method, url: String, headers: &[(&str, &str)]) -> Result<Response, Error> {
+        self.request(method, url, headers, Duration::from_secs(1)).send()
+    }
+
+    // the same as `send`, but the long-polling requests need a longer timeout
+    pub fn send_timeout(
+        &self,
+        method: Method,
+        url: String,
+        headers: &[(&str, &str)],
+        timeout: Duration,
+    ) -> Result<Response, Error> {
+        self.request(method, url, headers, timeout).send()
+    }
+
+    // the same as `send`, but with a request body
+    pub fn send_body(
+        &self,
+        method: Method,
+        url: String,
+        headers: &[(&str, &str)],
+        body: String,
+    ) -> Result<Response, Error> {
+        self.request(method, url, headers, Duration::from_secs(1))
+            .body(body)
+            .send()
+    }
+
+    fn request(&self, method: Method, url: String, headers: &[(&str, &str)], timeout: Duration) -> RequestBuilder {
+        let mut req = self.client.request(method, url).timeout(timeout);
+        for (k, v) in headers {
+            req = req.header(*k, *v);
+        }
+        req
+    }
+
+    // query the termination status, the body of 200 is the termination time.
+    // if it can't be parsed, the instance is assumed to be released after the shortest `notice` of the provider
+    pub fn query(&self, url: String, notice: TimeDelta) -> Result<Status, Error> {
+        self.query_with(url, &[], notice)
+    }
+
+    // the same as `query`, but some metadata services require extra headers
+    pub fn query_with(&self, url: String, headers: &[(&str, &str)], notice: TimeDelta) -> Result<Status, Error> {
+        self.query_by(url, headers, notice, parse_time)
+    }
 
+    // the same as `query_with`, but the termination time is extracted from the body by `at`
+    pub fn query_by<F>(&self, url: String, headers: &[(&str, &str)], notice: TimeDelta, at: F) -> Result<Status, Error>
+    where
+        F: Fn(&str) -> Option<DateTime<Utc>>,
+    {
+        Spot::status_of(self.send(Method::GET, url, headers)?, notice, at)
+    }
+
+    // the termination status in a response, the body of 200 is parsed by `at`
+    pub fn status_of<F>(res: Response, notice: TimeDelta, at: F) -> Result<Status, Error>
+    where
+        F: Fn(&str) -> Option<DateTime<Utc>>,
+    {
         match res.status() {
-            StatusCode::OK => Ok(0),
-            StatusCode::NOT_FOUND => Ok(1),
+            StatusCode::OK => {
+                let text = res.text()?;
+                Ok(Status::Terminating { at: at(text.trim()).unwrap_or_else(|| {
+                    warn!("[spot instance] unknown termination time {text}, assume {}s later", notice.num_seconds());
+                    Utc::now() + notice
+                }) })
+            }
+            StatusCode::NOT_FOUND => Ok(Status::Normal),
             _ => {
-                error!("[spot instance] unknown error: {}", res.text()?);
-                Ok(2)
+                let text = res.text()?;
+                error!("[spot instance] unknown error: {}", text);
+                Ok(Status::Unknown(text))
+            }
+        }
+    }
+
+    // fetch the plain text of a metadata item.
+    // it returns None if the item doesn't exist (404), and an error for other statuses
+    pub fn text(&self, url: String) -> Result<Option<String>, Error> {
+        self.text_with(url, &[])
+    }
+
+    pub fn text_with(&self, url: String, headers: &[(&str, &str)]) -> Result<Option<String>, Error> {
+        Spot::text_of(self.send(Method::GET, url, headers)?)
+    }
+
+    // the plain text in a response, the same as `text`
+    pub fn text_of(res: Response) -> Result<Option<String>, Error> {
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let text = res.error_for_status()?.text()?;
+        Ok(Some(text.trim().to_string()))
+    }
+}
+
+impl Default for Spot {
+    fn default() -> Self {
+        Spot::new()
+    }
+}
+
+// the termination status of a spot instance
+#[derive(Debug, PartialEq, Clone)]
+pub enum Status {
+    // nothing happens
+    Normal,
+    // the instance will be released at the time
+    // if the metadata service doesn't tell the exact time, it is estimated conservatively
+    Terminating { at: DateTime<Utc> },
+    // the metadata service returns something unexpected
+    Unknown(String),
+}
+
+// parse the termination time returned by metadata services
+// example: 2015-01-05T18:02:00Z, Mon, 19 Sep 2016 18:29:47 GMT
+pub fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_rfc2822(s))
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+}
+
+// SpotProvider is implemented by every cloud that supports spot instances.
+// Adding a new cloud only requires a new implementation and a branch in `provider`.
+pub trait SpotProvider: Send + Sync {
+    // the type of alert sent when the instance is going to be released
+    fn code(&self) -> Code;
+
+    // query the termination status, including the termination time
+    fn status(&self) -> Result<Status, Error>;
+
+    // the id of current instance
+    fn instance_id(&self) -> Result<String, Error>;
+
+    // the type, the region and the zone of current instance, recorded in the interruption history.
+    // None if the metadata service doesn't provide them
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn region(&self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn zone(&self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    // the rest of the metadata attached to the alerts, see `Metadata`
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    // an event which is worth an alert but doesn't mean termination,
+    // e.g. the rebalance recommendation of aws. most providers have none
+    fn advisory(&self) -> Result<Option<Code>, Error> {
+        Ok(None)
+    }
+
+    // the details of the interruption notice attached to the alert, e.g. the action of aws.
+    // most providers have none
+    fn notes(&self) -> Result<Vec<String>, Error> {
+        Ok(vec![])
+    }
+
+    // tell the cloud that the local actions are done and the instance can be reclaimed now.
+    // most providers don't support it, so do nothing
+    fn acknowledge(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// the metadata of current instance, fetched once on startup and attached to the alerts as labels.
+// the items which can't be fetched are left empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub instance_id: Option<String>,
+    pub instance_type: Option<String>,
+    pub region: Option<String>,
+    pub zone: Option<String>,
+    pub private_ip: Option<String>,
+    pub public_ip: Option<String>,
+    pub image_id: Option<String>,
+}
+
+impl Metadata {
+    pub fn fetch(provider: &dyn SpotProvider) -> Metadata {
+        let item = |name: &str, r: Result<Option<String>, Error>| match r {
+            Ok(v) => v.filter(|v| !v.is_empty()),
+            Err(err) => {
+                warn!("spot - failed to fetch the metadata {name}: {err}");
+                None
+            }
+        };
+        Metadata {
+            instance_id: item("instance_id", provider.instance_id().map(Some)),
+            instance_type: item("instance_type", provider.instance_type()),
+            region: item("region", provider.region()),
+            zone: item("zone", provider.zone()),
+            private_ip: item("private_ip", provider.private_ip()),
+            public_ip: item("public_ip", provider.public_ip()),
+            image_id: item("image_id", provider.image_id()),
+        }
+    }
+
+    // the known items in a fixed order
+    pub fn labels(&self) -> Labels {
+        [
+            ("instance_id", &self.instance_id),
+            ("instance_type", &self.instance_type),
+            ("region", &self.region),
+            ("zone", &self.zone),
+            ("private_ip", &self.private_ip),
+            ("public_ip", &self.public_ip),
+            ("image_id", &self.image_id),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| (k.to_string(), v.clone())))
+        .collect()
+    }
+}
+
+// create the provider matching the configuration, None means that there is nothing to monitor
+pub fn provider(p: &config::Provider, conf: &config::Spot) -> Option<Box<dyn SpotProvider>> {
+    match p {
+        config::Provider::AliCloud => Some(Box::new(AliCloud::new())),
+        config::Provider::TencentCloud => Some(Box::new(TencentCloud::new())),
+        config::Provider::HuaweiCloud => Some(Box::new(HuaweiCloud::new())),
+        config::Provider::Volcengine => Some(Box::new(Volcengine::new())),
+        config::Provider::AwsEc2 => Some(Box::new(AwsEc2::new())),
+        config::Provider::Gcp => Some(Box::new(Gcp::new())),
+        config::Provider::Azure => Some(Box::new(Azure::new().with_acknowledge(conf.acknowledge))),
+        config::Provider::Auto => provider(&detect(), conf),
+        config::Provider::LocalHost => None,
+    }
+}
+
+// the state of spot instance seen by the patrol
+//   Normal -> Terminating -> Terminated
+//     ^___________|______________|  (cancelled)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum State {
+    #[default]
+    Normal,
+    Terminating(DateTime<Utc>),
+    Terminated,
+}
+
+// everything the patrol remembers between two ticks
+#[derive(Default)]
+struct Watch {
+    state: State,
+    advised: Option<Code>, // the last advisory sent
+    failures: u32,         // the number of failed queries in a row
+    launch: Option<Launch>, // the replacement of this instance, if any
+}
+
+// SpotPatrol checks the status of the spot instance at regular intervals,
+// and sends an alert once the instance is going to be released.
+// then the hooks run within the termination window, and their results are reported.
+// it keeps polling after that, so that a cancelled termination is reported as well,
+// and the replacement launched in the background is terminated.
+pub struct SpotPatrol {
+    interval: u64, // patrol interval
+    name: String,
+    alert: Arc<Alert>,
+    hooks: Pipeline,
+    degraded_after: u32, // 0 means never
+    recovery: Option<Arc<Recovery>>,
+    history: Option<History>,
+    labels: Labels,
+}
+
+impl SpotPatrol {
+    pub fn new(interval: u64, name: String, alert: Arc<Alert>) -> SpotPatrol {
+        SpotPatrol {
+            interval,
+            name,
+            alert,
+            hooks: Pipeline::new(vec![]),
+            degraded_after: 3,
+            recovery: None,
+            history: None,
+            labels: vec![],
+        }
+    }
+
+    pub fn with_hooks(mut self, hooks: Pipeline) -> SpotPatrol {
+        self.hooks = hooks;
+        self
+    }
+
+    // alert once the status can't be queried for `n` times in a row, 0 disables it
+    pub fn with_degraded_after(mut self, n: u32) -> SpotPatrol {
+        self.degraded_after = n;
+        self
+    }
+
+    // launch a replacement once the instance is going to be released
+    pub fn with_recovery(mut self, recovery: Recovery) -> SpotPatrol {
+        self.recovery = Some(Arc::new(recovery));
+        self
+    }
+
+    // record the interruptions, which are used by `ic history` and `ic spot advise`
+    pub fn with_history(mut self, history: History) -> SpotPatrol {
+        self.history = Some(history);
+        self
+    }
+
+    // attach the labels to every alert, usually `Metadata::labels`
+    pub fn with_labels(mut self, labels: Labels) -> SpotPatrol {
+        self.labels = labels;
+        self
+    }
+
+    // keep polling forever, even after an interruption is detected
+    pub fn patrol(&self, provider: &dyn SpotProvider) {
+        let mut watch = Watch::default();
+        // super loop
+        loop {
+            self.tick(provider, &mut watch);
+            // delay
+            thread::sleep(Duration::from_secs(self.interval));
+        }
+    }
+
+    // a single patrol, which moves the state machine forward
+    fn tick(&self, provider: &dyn SpotProvider, watch: &mut Watch) {
+        // every new advisory is sent once
+        match provider.advisory() {
+            Ok(Some(c)) if watch.advised != Some(c) => {
+                self.send(self.msg(c));
+                watch.advised = Some(c);
+            }
+            Ok(Some(_)) => (),
+            Ok(None) => watch.advised = None,
+            Err(err) => error!("spot - advisory query error: {}", err),
+        };
+
+        // both network errors and unexpected responses mean we are blind to interruptions
+        let status = match provider.status() {
+            Ok(Status::Unknown(u)) => {
+                error!("spot - unknown error: {u}");
+                return self.fail(watch, &u);
+            }
+            Ok(status) => status,
+            Err(err) => {
+                error!("spot - query error: {}", err);
+                return self.fail(watch, &err.to_string());
+            }
+        };
+        if self.degraded_after > 0 && watch.failures >= self.degraded_after {
+            info!("spot - the query is recovered after {} failures", watch.failures);
+            let note = format!("此前连续{}次查询失败", watch.failures);
+            self.send(self.msg(Code::MonitorRecovered).with_note(&note));
+        }
+        watch.failures = 0;
+
+        watch.state = match (watch.state, status) {
+            // will be released in a few minutes
+            (State::Normal, Status::Terminating { at }) => {
+                info!("spot - the instance will be terminated at {at}");
+                let notes = provider.notes().unwrap_or_else(|err| {
+                    error!("spot - notice query error: {}", err);
+                    vec![]
+                });
+                let msg = notes.iter().fold(self.msg(provider.code()), |m, n| m.with_note(n));
+                self.send(msg.with_termination(at));
+                let outcomes = self.run_hooks(at);
+                self.record(provider, at, &outcomes);
+                if let Err(err) = provider.acknowledge() {
+                    error!("spot - acknowledge error: {}", err);
+                }
+                watch.launch = self.recover(provider);
+                State::Terminating(at)
+            }
+            // the termination time has passed, but the instance is still alive
+            (State::Terminating(at), Status::Terminating { .. }) if Utc::now() > at => {
+                warn!("spot - the termination time {at} has passed");
+                State::Terminated
+            }
+            // the notice is withdrawn
+            (State::Terminating(_) | State::Terminated, Status::Normal) => {
+                info!("spot - the termination is cancelled");
+                self.send(self.msg(Code::SpotCancelled));
+                if let Some(launch) = watch.launch.take() {
+                    launch.withdraw();
+                }
+                State::Normal
+            }
+            (State::Normal, Status::Normal) => {
+                info!("everything is ok with this server");
+                State::Normal
+            }
+            (state, _) => state,
+        };
+    }
+
+    // count a failed query, and alert once the threshold is reached
+    fn fail(&self, watch: &mut Watch, reason: &str) {
+        watch.failures = watch.failures.saturating_add(1);
+        if watch.failures == self.degraded_after {
+            let note = format!("连续{}次查询失败：{}", watch.failures, reason);
+            self.send(self.msg(Code::MonitorDegraded).with_note(&note));
+        }
+    }
+
+    // append the interruption to the history, the missing metadata is left empty.
+    // the hooks are bounded by the termination time, so there is still time to record
+    fn record(&self, provider: &dyn SpotProvider, at: DateTime<Utc>, outcomes: &[Outcome]) {
+        let Some(history) = self.history.as_ref() else {
+            return;
+        };
+        let code = provider.code();
+        let record = Record {
+            time: Utc::now(),
+            code,
+            provider: code.provider().map(|p| p.to_string()),
+            instance_id: provider.instance_id().unwrap_or_default(),
+            instance_type: provider.instance_type().ok().flatten(),
+            region: provider.region().ok().flatten(),
+            zone: provider.zone().ok().flatten(),
+            boot: DateTime::from_timestamp(System::boot_time() as i64, 0),
+            termination: Some(at),
+            hooks: outcomes
+                .iter()
+                .map(|o| HookRecord {
+                    name: o.name.clone(),
+                    elapsed: o.elapsed.as_secs_f64(),
+                    error: o.result.clone().err(),
+                })
+                .collect(),
+        };
+        if let Err(err) = history.append(&record) {
+            error!("spot - record the interruption error: {}", err);
+        }
+    }
+
+    // replace this instance with the same tags in the background, they are described through the api
+    fn recover(&self, provider: &dyn SpotProvider) -> Option<Launch> {
+        let recovery = self.recovery.as_ref()?;
+        match provider.instance_id() {
+            Ok(id) => Some(recovery.launch(Arc::clone(&self.alert), &id, None)),
+            Err(err) => {
+                error!("spot - instance id error: {}", err);
+                None
             }
         }
     }
 
-    // query the interruption events of a preemptive instance of aliyun (alias ecs)
-    // fixed url: GET http://100.100.100.200/latest/meta-data/instance/spot/termination-time
-    // there are the following results
-    // 0: it means that the instance will be released in a few minutes.
-    // 1: normal
-    // 2. unknown error
-    // reference: https://help.aliyun.com/zh/ecs/use-cases/query-the-interruption-events-of-preemptible-instances
-    pub fn query_ecs(&self) -> Result<i8, Error> {
-        self.query(
-            "http://100.100.100.200/latest/meta-data/instance/spot/termination-time".to_string(),
-        )
+    // run the hooks and report the outcomes in a follow-up alert
+    fn run_hooks(&self, at: DateTime<Utc>) -> Vec<Outcome> {
+        if self.hooks.is_empty() {
+            return vec![];
+        }
+        let outcomes = self.hooks.run(at);
+        let mut msg = self.msg(Code::SpotHookReport).with_termination(at);
+        for outcome in outcomes.iter() {
+            msg = msg.with_note(&outcome.to_string());
+        }
+        self.send(msg);
+        outcomes
+    }
+
+    // a message about this instance
+    fn msg(&self, code: Code) -> Msg {
+        Msg::new(code, Myself(self.name.clone())).with_labels(self.labels.clone())
     }
 
-    // the spot instance of tencentcloud (alias cvm)
-    // fixed url: GET http://metadata.tencentyun.com/latest/meta-data/spot/termination-time
-    // reference: https://cloud.tencent.com/document/product/213/37970
-    pub fn query_cvm(&self) -> Result<i8, Error> {
-        self.query(
-            "http://metadata.tencentyun.com/latest/meta-data/spot/termination-time".to_string(),
-        )
+    // send an alert in a child thread, so that the patrol isn't blocked
+    fn send(&self, msg: Msg) {
+        // clone a copy
+        let alert = Arc::clone(&self.alert);
+        thread::spawn(move || alert.send(&msg));
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::alert::{AlertMap, Notice};
+    use crate::controller::{self, CloudApi, Instance, Tags};
+    use chrono::{TimeDelta, TimeZone};
+    use std::collections::{HashMap, VecDeque};
+    use std::time::Instant;
+    use std::sync::Mutex;
 
     // network error
     #[test]
     fn test_query_err() {
         let spot = Spot::new();
         let err = spot
-            .query("http://100.100.100.200".to_string())
+            .query("http://100.100.100.200".to_string(), TimeDelta::minutes(2))
             .expect_err("timeout");
         assert!(err.is_timeout());
     }
 
-    fn test_query(status: usize, code: i8) {
+    fn test_query(status: usize, body: &str, expected: Status) {
         let mut server = mockito::Server::new();
 
-        let mock = server.mock("GET", "/spot").with_status(status).create();
+        let mock = server.mock("GET", "/spot").with_status(status).with_body(body).create();
         let spot = Spot::new();
-        let c = spot.query(format!("{}/spot", server.url())).unwrap();
-        assert_eq!(c, code);
+        let c = spot.query(format!("{}/spot", server.url()), TimeDelta::minutes(2)).unwrap();
+        assert_eq!(c, expected);
         mock.assert();
     }
 
     // (true) will be released
     #[test]
     fn test_query_0() {
-        test_query(200, 0);
+        let at = Utc.with_ymd_and_hms(2015, 1, 5, 18, 2, 0).unwrap();
+        test_query(200, "2015-01-05T18:02:00Z", Status::Terminating { at });
     }
 
     #[test]
     fn test_query_1() {
-        test_query(404, 1);
+        test_query(404, "", Status::Normal);
     }
 
     #[test]
     fn test_query_2() {
-        test_query(500, 2);
+        test_query(500, "oops", Status::Unknown("oops".to_string()));
+    }
+
+    // the time can't be parsed, so the shortest notice is assumed
+    #[test]
+    fn test_query_unknown_time() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/spot").with_body("soon").create();
+        let spot = Spot::new();
+        match spot.query(format!("{}/spot", server.url()), TimeDelta::minutes(2)).unwrap() {
+            Status::Terminating { at } => assert!((Utc::now() + TimeDelta::minutes(2) - at).num_seconds() < 5),
+            s => panic!("unexpected status: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let at = Utc.with_ymd_and_hms(2016, 9, 19, 18, 29, 47).unwrap();
+        assert_eq!(parse_time("2016-09-19T18:29:47Z"), Some(at));
+        assert_eq!(parse_time("2016-09-20T02:29:47+08:00"), Some(at));
+        assert_eq!(parse_time("Mon, 19 Sep 2016 18:29:47 GMT"), Some(at));
+        assert_eq!(parse_time("TRUE"), None);
+    }
+
+    #[test]
+    fn test_text() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/ok").with_body("i-001\n").create();
+        server.mock("GET", "/missing").with_status(404).create();
+        server.mock("GET", "/error").with_status(500).create();
+
+        let spot = Spot::new();
+        let text = spot.text(format!("{}/ok", server.url())).unwrap();
+        assert_eq!(text, Some("i-001".to_string()));
+        assert_eq!(spot.text(format!("{}/missing", server.url())).unwrap(), None);
+        assert!(spot.text(format!("{}/error", server.url())).is_err());
+    }
+
+    // a fake provider which returns the scripted statuses and advisories in order,
+    // None means a network error
+    struct Fake {
+        statuses: Mutex<VecDeque<Option<Status>>>,
+        advisories: Mutex<VecDeque<Option<Code>>>,
+    }
+
+    impl Fake {
+        fn new(statuses: Vec<Status>, advisories: Vec<Option<Code>>) -> Fake {
+            Fake::script(statuses.into_iter().map(Some).collect(), advisories)
+        }
+
+        fn script(statuses: Vec<Option<Status>>, advisories: Vec<Option<Code>>) -> Fake {
+            Fake {
+                statuses: Mutex::new(statuses.into()),
+                advisories: Mutex::new(advisories.into()),
+            }
+        }
+    }
+
+    impl SpotProvider for Fake {
+        fn code(&self) -> Code {
+            Code::AwsInterrupt
+        }
+
+        fn status(&self) -> Result<Status, Error> {
+            match self.statuses.lock().unwrap().pop_front() {
+                // an invalid url fails at once without any network
+                Some(None) => Err(reqwest::blocking::get("not a url").unwrap_err()),
+                Some(Some(status)) => Ok(status),
+                None => Ok(Status::Normal),
+            }
+        }
+
+        fn instance_id(&self) -> Result<String, Error> {
+            Ok("i-fake".to_string())
+        }
+
+        fn advisory(&self) -> Result<Option<Code>, Error> {
+            Ok(self.advisories.lock().unwrap().pop_front().flatten())
+        }
+    }
+
+    // the codes sent, and whether the termination time is attached
+    type Codes = Arc<Mutex<Vec<(Code, bool)>>>;
+
+    struct Recorder {
+        codes: Codes,
+    }
+
+    impl Notice for Recorder {
+        fn send(&self, msg: &Msg) -> Result<(), Box<dyn std::error::Error>> {
+            self.codes.lock().unwrap().push((msg.code(), msg.termination().is_some()));
+            Ok(())
+        }
+    }
+
+    // run the patrol tick by tick, and return the codes sent
+    fn patrol(sp: SpotPatrol, fake: &Fake, ticks: usize, codes: Codes) -> Vec<(Code, bool)> {
+        let mut watch = Watch::default();
+        for _ in 0..ticks {
+            sp.tick(fake, &mut watch);
+        }
+        // delay 100ms to allow the alerts are sent
+        thread::sleep(Duration::from_millis(100));
+        let mut codes = codes.lock().unwrap().clone();
+        codes.sort_by_key(|(c, _)| c.to_string());
+        codes
+    }
+
+    fn patrol_with(hooks: Pipeline) -> (SpotPatrol, Codes) {
+        let codes = Arc::new(Mutex::new(vec![]));
+        let mut map = AlertMap::new();
+        map.insert("r".to_string(), Box::new(Recorder { codes: Arc::clone(&codes) }));
+        let sp = SpotPatrol::new(0, "Q".to_string(), Arc::new(Alert::new(map))).with_hooks(hooks);
+        (sp, codes)
+    }
+
+    #[test]
+    fn test_patrol() {
+        let (sp, codes) = patrol_with(Pipeline::new(vec![]));
+        let later = Utc::now() + TimeDelta::seconds(120);
+        let fake = Fake::new(
+            vec![Status::Normal, Status::Normal, Status::Terminating { at: later }, Status::Terminating { at: later }],
+            vec![Some(Code::AwsRebalance), Some(Code::AwsRebalance)],
+        );
+        let codes = patrol(sp, &fake, 4, codes);
+        // the advisory and the interruption are sent only once
+        assert_eq!(codes, vec![(Code::AwsInterrupt, true), (Code::AwsRebalance, false)]);
+    }
+
+    #[test]
+    fn test_patrol_cancelled() {
+        let (sp, codes) = patrol_with(Pipeline::new(vec![]));
+        let later = Utc::now() + TimeDelta::seconds(120);
+        let fake = Fake::new(
+            vec![Status::Terminating { at: later }, Status::Normal, Status::Terminating { at: later }],
+            vec![],
+        );
+        let codes = patrol(sp, &fake, 3, codes);
+        // terminating -> cancelled -> terminating again
+        assert_eq!(codes, vec![(Code::AwsInterrupt, true), (Code::AwsInterrupt, true), (Code::SpotCancelled, false)]);
+    }
+
+    #[test]
+    fn test_patrol_terminated() {
+        let (sp, _) = patrol_with(Pipeline::new(vec![]));
+        let earlier = Utc::now() - TimeDelta::seconds(1);
+        let fake = Fake::new(vec![Status::Terminating { at: earlier }, Status::Terminating { at: earlier }], vec![]);
+        let mut watch = Watch::default();
+        sp.tick(&fake, &mut watch);
+        assert_eq!(watch.state, State::Terminating(earlier));
+        sp.tick(&fake, &mut watch);
+        assert_eq!(watch.state, State::Terminated);
+    }
+
+    #[test]
+    fn test_patrol_degraded() {
+        let (sp, codes) = patrol_with(Pipeline::new(vec![]));
+        let unknown = || Status::Unknown("oops".to_string());
+        let fake = Fake::new(
+            vec![unknown(), unknown(), Status::Normal, unknown(), unknown(), unknown(), unknown()],
+            vec![],
+        );
+        let codes = patrol(sp, &fake, 7, codes);
+        // it is reset by a normal result, and sent once
+        assert_eq!(codes, vec![(Code::MonitorDegraded, false)]);
+    }
+
+    #[test]
+    fn test_patrol_unreachable() {
+        let (sp, codes) = patrol_with(Pipeline::new(vec![]));
+        let sp = sp.with_degraded_after(2);
+        let unknown = Some(Status::Unknown("oops".to_string()));
+        let fake = Fake::script(vec![None, unknown, None, None, Some(Status::Normal), Some(Status::Normal)], vec![]);
+        let codes = patrol(sp, &fake, 6, codes);
+        // network errors and unknown results are counted together, then recovered once
+        assert_eq!(codes, vec![(Code::MonitorDegraded, false), (Code::MonitorRecovered, false)]);
+    }
+
+    #[test]
+    fn test_patrol_never_degraded() {
+        let (sp, codes) = patrol_with(Pipeline::new(vec![]));
+        let sp = sp.with_degraded_after(0);
+        let fake = Fake::script(vec![None, None, None, None, Some(Status::Normal)], vec![]);
+        assert_eq!(patrol(sp, &fake, 5, codes), vec![]);
+    }
+
+    // a cloud whose replacements never run
+    #[derive(Clone, Default)]
+    struct Cloud {
+        launched: Arc<Mutex<Vec<String>>>,
+        terminated: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CloudApi for Cloud {
+        fn code(&self) -> Code {
+            Code::AwsInterrupt
+        }
+
+        fn region(&self) -> String {
+            "us-east-1".to_string()
+        }
+
+        fn describe(&self, ids: &[String]) -> Result<HashMap<String, Instance>, Box<dyn std::error::Error>> {
+            let instance = Instance { state: controller::State::Stopped, ..Default::default() };
+            Ok(ids.iter().map(|id| (id.clone(), instance.clone())).collect())
+        }
+
+        fn run_instance(&self, _template: &str, _tags: &Tags) -> Result<String, Box<dyn std::error::Error>> {
+            self.launched.lock().unwrap().push("i-new".to_string());
+            Ok("i-new".to_string())
+        }
+
+        fn terminate_instance(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+            self.terminated.lock().unwrap().push(id.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_patrol_recover() {
+        let cloud = Cloud::default();
+        let recovery = Recovery::new(Box::new(cloud.clone()), "lt-1", Duration::from_secs(60))
+            .with_poll(Duration::from_millis(10));
+        let (sp, _) = patrol_with(Pipeline::new(vec![]));
+        let sp = sp.with_recovery(recovery);
+        let fake = Fake::new(vec![Status::Terminating { at: Utc::now() + TimeDelta::seconds(60) }, Status::Normal], vec![]);
+        let mut watch = Watch::default();
+
+        // the patrol isn't blocked by the launch
+        let start = Instant::now();
+        sp.tick(&fake, &mut watch);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(*cloud.launched.lock().unwrap(), vec!["i-new"]);
+
+        // the replacement is terminated once the termination is cancelled
+        sp.tick(&fake, &mut watch);
+        assert!(watch.launch.is_none());
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(*cloud.terminated.lock().unwrap(), vec!["i-new"]);
+    }
+
+    #[test]
+    fn test_patrol_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let hooks = Pipeline::new(vec![config::Step {
+            action: config::Action::Command { command: "false".to_string(), args: vec![] },
+            timeout: None,
+        }]);
+        let (sp, _) = patrol_with(hooks);
+        let sp = sp.with_history(History::new(&path));
+        let at = Utc::now() + TimeDelta::seconds(60);
+        let fake = Fake::new(vec![Status::Terminating { at }, Status::Terminating { at }], vec![]);
+        patrol(sp, &fake, 2, Arc::new(Mutex::new(vec![])));
+
+        // recorded only once
+        let records = History::new(&path).load().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].code, Code::AwsInterrupt);
+        assert_eq!(records[0].instance_id, "i-fake");
+        assert_eq!(records[0].provider.as_deref(), Some("AwsEc2"));
+        assert_eq!(records[0].instance_type, None);
+        assert_eq!(records[0].termination, Some(at));
+        assert!(records[0].boot.is_some_and(|b| b < records[0].time));
+        // the outcomes of the hooks are recorded as well
+        assert_eq!(records[0].hooks.len(), 1);
+        assert!(records[0].hooks[0].error.is_some());
+    }
+
+    #[test]
+    fn test_patrol_hooks() {
+        let hooks = Pipeline::new(vec![config::Step {
+            action: config::Action::Command { command: "true".to_string(), args: vec![] },
+            timeout: None,
+        }]);
+        let (sp, codes) = patrol_with(hooks);
+        let fake = Fake::new(vec![Status::Terminating { at: Utc::now() + TimeDelta::seconds(60) }], vec![]);
+        let codes = patrol(sp, &fake, 1, codes);
+        assert!(codes.contains(&(Code::SpotHookReport, true)));
+    }
+
+    // the hooks still have the shortest notice of the provider, even if the time can't be parsed
+    #[test]
+    fn test_patrol_unknown_time() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/latest/meta-data/instance/spot/termination-time").with_body("soon").create();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let hooks = Pipeline::new(vec![config::Step {
+            action: config::Action::Command { command: "true".to_string(), args: vec![] },
+            timeout: None,
+        }]);
+        let (sp, _) = patrol_with(hooks);
+        let sp = sp.with_history(History::new(&path));
+        sp.tick(&AliCloud::with_base_url(&server.url()), &mut Watch::default());
+
+        let records = History::new(&path).load().unwrap();
+        assert_eq!(records[0].hooks.len(), 1);
+        assert_eq!(records[0].hooks[0].error, None);
+    }
+
+    #[test]
+    fn test_metadata() {
+        // only the instance id is known to the fake provider
+        let fake = Fake::new(vec![], vec![]);
+        let metadata = Metadata::fetch(&fake);
+        assert_eq!(metadata, Metadata { instance_id: Some("i-fake".to_string()), ..Default::default() });
+        assert_eq!(metadata.labels(), vec![("instance_id".to_string(), "i-fake".to_string())]);
+
+        let metadata = Metadata {
+            region: Some("cn-hangzhou".to_string()),
+            private_ip: Some("172.16.0.10".to_string()),
+            ..metadata
+        };
+        let keys: Vec<String> = metadata.labels().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["instance_id", "region", "private_ip"]);
+
+        // every alert of the patrol carries the labels
+        let (sp, _) = patrol_with(Pipeline::new(vec![]));
+        let sp = sp.with_labels(metadata.labels());
+        assert_eq!(sp.msg(Code::SpotCancelled).labels(), &metadata.labels());
+    }
+
+    #[test]
+    fn test_provider() {
+        let conf = config::Spot::default();
+        assert!(provider(&config::Provider::LocalHost, &conf).is_none());
+        let p = provider(&config::Provider::AliCloud, &conf).unwrap();
+        assert_eq!(p.code(), Code::AliCloudInterrupt);
+        let p = provider(&config::Provider::TencentCloud, &conf).unwrap();
+        assert_eq!(p.code(), Code::TencentCloudInterrupt);
+        let p = provider(&config::Provider::HuaweiCloud, &conf).unwrap();
+        assert_eq!(p.code(), Code::HuaweiCloudInterrupt);
+        let p = provider(&config::Provider::Volcengine, &conf).unwrap();
+        assert_eq!(p.code(), Code::VolcengineInterrupt);
+        let p = provider(&config::Provider::AwsEc2, &conf).unwrap();
+        assert_eq!(p.code(), Code::AwsInterrupt);
+        let p = provider(&config::Provider::Gcp, &conf).unwrap();
+        assert_eq!(p.code(), Code::GcpPreempted);
+        let p = provider(&config::Provider::Azure, &conf).unwrap();
+        assert_eq!(p.code(), Code::AzureInterrupt);
     }
 }
@@ -0,0 +1,162 @@
+use super::{Spot, SpotProvider, Status};
+use crate::alert::Code;
+use chrono::TimeDelta;
+use reqwest::Error;
+
+// the metadata service of aliyun ecs
+pub const BASE_URL: &str = "http://100.100.100.200";
+
+// the notice before the release, assumed if the termination time can't be parsed
+const NOTICE: TimeDelta = TimeDelta::minutes(5);
+
+// the preemptible instance of aliyun (alias ecs)
+// reference: https://help.aliyun.com/zh/ecs/use-cases/query-the-interruption-events-of-preemptible-instances
+pub struct AliCloud {
+    spot: Spot,
+    base_url: String,
+}
+
+impl AliCloud {
+    pub fn new() -> AliCloud {
+        AliCloud::with_base_url(BASE_URL)
+    }
+
+    // the base url can be overridden, usually for testing
+    pub fn with_base_url(base_url: &str) -> AliCloud {
+        AliCloud {
+            spot: Spot::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/latest/meta-data/{path}", self.base_url)
+    }
+}
+
+impl Default for AliCloud {
+    fn default() -> Self {
+        AliCloud::new()
+    }
+}
+
+impl SpotProvider for AliCloud {
+    fn code(&self) -> Code {
+        Code::AliCloudInterrupt
+    }
+
+    // GET /latest/meta-data/instance/spot/termination-time
+    // example: 2015-01-05T18:02:00Z
+    fn status(&self) -> Result<Status, Error> {
+        self.spot.query(self.url("instance/spot/termination-time"), NOTICE)
+    }
+
+    // GET /latest/meta-data/instance-id
+    fn instance_id(&self) -> Result<String, Error> {
+        Ok(self.spot.text(self.url("instance-id"))?.unwrap_or_default())
+    }
+
+    // GET /latest/meta-data/instance/instance-type
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("instance/instance-type"))
+    }
+
+    // GET /latest/meta-data/region-id
+    fn region(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("region-id"))
+    }
+
+    // GET /latest/meta-data/zone-id
+    fn zone(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("zone-id"))
+    }
+
+    // GET /latest/meta-data/private-ipv4
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("private-ipv4"))
+    }
+
+    // GET /latest/meta-data/eipv4, the public ip or the elastic ip
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("eipv4"))
+    }
+
+    // GET /latest/meta-data/image-id
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("image-id"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_status() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/latest/meta-data/instance/spot/termination-time")
+            .with_body("2015-01-05T18:02:00Z")
+            .create();
+
+        let ecs = AliCloud::with_base_url(&format!("{}/", server.url()));
+        let at = Utc.with_ymd_and_hms(2015, 1, 5, 18, 2, 0).unwrap();
+        assert_eq!(ecs.status().unwrap(), Status::Terminating { at });
+        mock.assert();
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/latest/meta-data/instance/spot/termination-time")
+            .with_status(404)
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/instance-id")
+            .with_body("i-bp1e8q2b0xyz")
+            .create();
+
+        let ecs = AliCloud::with_base_url(&server.url());
+        assert_eq!(ecs.status().unwrap(), Status::Normal);
+        assert_eq!(ecs.instance_id().unwrap(), "i-bp1e8q2b0xyz");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/latest/meta-data/instance/instance-type")
+            .with_body("ecs.g7.large")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/region-id")
+            .with_body("cn-hangzhou")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/zone-id")
+            .with_body("cn-hangzhou-i")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/private-ipv4")
+            .with_body("172.16.0.10")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/eipv4")
+            .with_body("47.96.0.1")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/image-id")
+            .with_body("aliyun_3_x64_20G_alibase_20240528.vhd")
+            .create();
+
+        let ecs = AliCloud::with_base_url(&server.url());
+        assert_eq!(ecs.instance_type().unwrap(), Some("ecs.g7.large".to_string()));
+        assert_eq!(ecs.region().unwrap(), Some("cn-hangzhou".to_string()));
+        assert_eq!(ecs.zone().unwrap(), Some("cn-hangzhou-i".to_string()));
+        assert_eq!(ecs.private_ip().unwrap(), Some("172.16.0.10".to_string()));
+        assert_eq!(ecs.public_ip().unwrap(), Some("47.96.0.1".to_string()));
+        assert_eq!(ecs.image_id().unwrap(), Some("aliyun_3_x64_20G_alibase_20240528.vhd".to_string()));
+    }
+}
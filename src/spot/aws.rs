@@ -0,0 +1,327 @@
+use super::{parse_time, Spot, SpotProvider, Status};
+use crate::alert::Code;
+use chrono::TimeDelta;
+use log::warn;
+use reqwest::blocking::Response;
+use reqwest::{Error, Method, StatusCode};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// the instance metadata service (IMDS) of aws ec2
+pub const BASE_URL: &str = "http://169.254.169.254";
+
+// the notice before the interruption, assumed if the time of the notice can't be parsed
+const NOTICE: TimeDelta = TimeDelta::minutes(2);
+
+// the session token lives for 6 hours, it will be refreshed a minute in advance
+const TOKEN_TTL: u64 = 21600;
+
+// the interruption notice of a spot instance
+// example: {"action": "terminate", "time": "2017-09-18T08:22:00Z"}
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct InstanceAction {
+    pub action: Action,
+    pub time: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Terminate,
+    Stop,
+    Hibernate,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Action::Terminate => "终止",
+            Action::Stop => "停止",
+            Action::Hibernate => "休眠",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// the spot instance of aws ec2, only IMDSv2 is supported
+// reference: https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/spot-instance-termination-notices.html
+// reference: https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/rebalance-recommendations.html
+pub struct AwsEc2 {
+    spot: Spot,
+    base_url: String,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl AwsEc2 {
+    pub fn new() -> AwsEc2 {
+        AwsEc2::with_base_url(BASE_URL)
+    }
+
+    // the base url can be overridden, usually for testing
+    pub fn with_base_url(base_url: &str) -> AwsEc2 {
+        AwsEc2 {
+            spot: Spot::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: Mutex::new(None),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/latest/meta-data/{path}", self.base_url)
+    }
+
+    // PUT /latest/api/token, the token is cached until it is about to expire
+    fn token(&self) -> Result<String, Error> {
+        let mut token = self.token.lock().unwrap();
+        if let Some((t, created)) = token.as_ref() {
+            if created.elapsed() < Duration::from_secs(TOKEN_TTL - 60) {
+                return Ok(t.to_string());
+            }
+        }
+        let ttl = TOKEN_TTL.to_string();
+        let t = self
+            .spot
+            .send(
+                Method::PUT,
+                format!("{}/latest/api/token", self.base_url),
+                &[("X-aws-ec2-metadata-token-ttl-seconds", &ttl)],
+            )?
+            .error_for_status()?
+            .text()?;
+        *token = Some((t.clone(), Instant::now()));
+        Ok(t)
+    }
+
+    // GET with the token. the token is lost once the metadata service restarts,
+    // so a rejected one is dropped and the request is retried once with a new one
+    fn get(&self, path: &str) -> Result<Response, Error> {
+        let res = self.spot.send(Method::GET, self.url(path), &[("X-aws-ec2-metadata-token", &self.token()?)])?;
+        if res.status() != StatusCode::UNAUTHORIZED {
+            return Ok(res);
+        }
+        warn!("[aws] the token is rejected, request a new one");
+        *self.token.lock().unwrap() = None;
+        self.spot.send(Method::GET, self.url(path), &[("X-aws-ec2-metadata-token", &self.token()?)])
+    }
+
+    fn text(&self, path: &str) -> Result<Option<String>, Error> {
+        Spot::text_of(self.get(path)?)
+    }
+
+    // GET /latest/meta-data/spot/instance-action
+    // None if the instance is not going to be interrupted, or the notice can't be parsed
+    pub fn action(&self) -> Result<Option<InstanceAction>, Error> {
+        Ok(self.text("spot/instance-action")?.and_then(|t| {
+            serde_json::from_str(&t)
+                .map_err(|err| log::error!("[aws] invalid instance action {t}: {err}"))
+                .ok()
+        }))
+    }
+}
+
+impl Default for AwsEc2 {
+    fn default() -> Self {
+        AwsEc2::new()
+    }
+}
+
+impl SpotProvider for AwsEc2 {
+    fn code(&self) -> Code {
+        Code::AwsInterrupt
+    }
+
+    // GET /latest/meta-data/spot/instance-action, the time is taken from the notice
+    fn status(&self) -> Result<Status, Error> {
+        Spot::status_of(self.get("spot/instance-action")?, NOTICE, |body| {
+            serde_json::from_str::<InstanceAction>(body)
+                .ok()
+                .and_then(|a| parse_time(&a.time))
+        })
+    }
+
+    // the action taken on the instance, it may be stopped or hibernated instead of terminated
+    fn notes(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .action()?
+            .map(|a| vec![format!("中断行为：{}", a.action)])
+            .unwrap_or_default())
+    }
+
+    // GET /latest/meta-data/instance-id
+    fn instance_id(&self) -> Result<String, Error> {
+        Ok(self.text("instance-id")?.unwrap_or_default())
+    }
+
+    // GET /latest/meta-data/instance-type
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        self.text("instance-type")
+    }
+
+    // GET /latest/meta-data/placement/region
+    fn region(&self) -> Result<Option<String>, Error> {
+        self.text("placement/region")
+    }
+
+    // GET /latest/meta-data/placement/availability-zone
+    fn zone(&self) -> Result<Option<String>, Error> {
+        self.text("placement/availability-zone")
+    }
+
+    // GET /latest/meta-data/local-ipv4
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        self.text("local-ipv4")
+    }
+
+    // GET /latest/meta-data/public-ipv4, None if the instance has no public ip
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        self.text("public-ipv4")
+    }
+
+    // GET /latest/meta-data/ami-id
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        self.text("ami-id")
+    }
+
+    // GET /latest/meta-data/events/recommendations/rebalance
+    // example: {"noticeTime": "2020-11-05T08:22:00Z"}
+    fn advisory(&self) -> Result<Option<Code>, Error> {
+        Ok(self
+            .text("events/recommendations/rebalance")?
+            .map(|_| Code::AwsRebalance))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use mockito::{Matcher, Server};
+
+    // mock the IMDSv2 token api, it must be requested with PUT
+    fn mock_token(server: &mut Server) -> mockito::Mock {
+        server
+            .mock("PUT", "/latest/api/token")
+            .match_header("X-aws-ec2-metadata-token-ttl-seconds", "21600")
+            .with_body("AQAEAEXAMPLE")
+            .create()
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut server = Server::new();
+        // the token is cached, so it is requested only once
+        let token = mock_token(&mut server);
+        let mock = server
+            .mock("GET", "/latest/meta-data/spot/instance-action")
+            .match_header("X-aws-ec2-metadata-token", "AQAEAEXAMPLE")
+            .with_body(r#"{"action": "stop", "time": "2017-09-18T08:22:00Z"}"#)
+            .expect(3)
+            .create();
+
+        let ec2 = AwsEc2::with_base_url(&server.url());
+        let at = Utc.with_ymd_and_hms(2017, 9, 18, 8, 22, 0).unwrap();
+        assert_eq!(ec2.status().unwrap(), Status::Terminating { at });
+        assert_eq!(ec2.action().unwrap(), Some(InstanceAction {
+            action: Action::Stop,
+            time: "2017-09-18T08:22:00Z".to_string(),
+        }));
+        assert_eq!(ec2.notes().unwrap(), ["中断行为：停止"]);
+        token.assert();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut server = Server::new();
+        mock_token(&mut server);
+        server
+            .mock("GET", Matcher::Regex("^/latest/meta-data/(spot|events)/".to_string()))
+            .with_status(404)
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/instance-id")
+            .with_body("i-1234567890abcdef0")
+            .create();
+
+        let ec2 = AwsEc2::with_base_url(&server.url());
+        assert_eq!(ec2.status().unwrap(), Status::Normal);
+        assert_eq!(ec2.action().unwrap(), None);
+        assert_eq!(ec2.advisory().unwrap(), None);
+        assert_eq!(ec2.instance_id().unwrap(), "i-1234567890abcdef0");
+    }
+
+    #[test]
+    fn test_rebalance() {
+        let mut server = Server::new();
+        mock_token(&mut server);
+        server
+            .mock("GET", "/latest/meta-data/events/recommendations/rebalance")
+            .with_body(r#"{"noticeTime": "2020-11-05T08:22:00Z"}"#)
+            .create();
+
+        let ec2 = AwsEc2::with_base_url(&server.url());
+        assert_eq!(ec2.advisory().unwrap(), Some(Code::AwsRebalance));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut server = Server::new();
+        mock_token(&mut server);
+        for (path, body) in [
+            ("instance-type", "m5.large"),
+            ("placement/region", "us-east-1"),
+            ("placement/availability-zone", "us-east-1a"),
+            ("local-ipv4", "10.0.0.12"),
+            ("ami-id", "ami-0abcdef1234567890"),
+        ] {
+            server.mock("GET", format!("/latest/meta-data/{path}").as_str()).with_body(body).create();
+        }
+        server.mock("GET", "/latest/meta-data/public-ipv4").with_status(404).create();
+
+        let ec2 = AwsEc2::with_base_url(&server.url());
+        assert_eq!(ec2.instance_type().unwrap(), Some("m5.large".to_string()));
+        assert_eq!(ec2.region().unwrap(), Some("us-east-1".to_string()));
+        assert_eq!(ec2.zone().unwrap(), Some("us-east-1a".to_string()));
+        assert_eq!(ec2.private_ip().unwrap(), Some("10.0.0.12".to_string()));
+        assert_eq!(ec2.public_ip().unwrap(), None);
+        assert_eq!(ec2.image_id().unwrap(), Some("ami-0abcdef1234567890".to_string()));
+    }
+
+    // the cached token is lost after the metadata service restarts
+    #[test]
+    fn test_token_expired() {
+        let mut server = Server::new();
+        let token = mock_token(&mut server);
+        let rejected = server
+            .mock("GET", "/latest/meta-data/spot/instance-action")
+            .match_header("X-aws-ec2-metadata-token", "STALE")
+            .with_status(401)
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/spot/instance-action")
+            .match_header("X-aws-ec2-metadata-token", "AQAEAEXAMPLE")
+            .with_status(404)
+            .expect(2)
+            .create();
+
+        let ec2 = AwsEc2::with_base_url(&server.url());
+        *ec2.token.lock().unwrap() = Some(("STALE".to_string(), Instant::now()));
+        assert_eq!(ec2.status().unwrap(), Status::Normal);
+        // the new token is cached
+        assert_eq!(ec2.status().unwrap(), Status::Normal);
+        rejected.assert();
+        token.assert();
+    }
+
+    // IMDSv1 is disabled or the token api is unavailable
+    #[test]
+    fn test_token_err() {
+        let mut server = Server::new();
+        server.mock("PUT", "/latest/api/token").with_status(403).create();
+
+        let ec2 = AwsEc2::with_base_url(&server.url());
+        assert!(ec2.status().is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use super::{parse_time, Spot, SpotProvider, Status};
+use crate::alert::Code;
+use chrono::{TimeDelta, Utc};
+use log::{error, warn};
+use reqwest::{Error, Method, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::{Mutex, OnceLock};
+
+// the instance metadata service (IMDS) of azure
+pub const BASE_URL: &str = "http://169.254.169.254";
+
+const API_VERSION: &str = "2020-07-01";
+
+// the version of the instance metadata
+const INSTANCE_API_VERSION: &str = "2021-02-01";
+
+// all the requests must carry this header
+const METADATA: (&str, &str) = ("Metadata", "true");
+
+// the document returned by the scheduled events endpoint
+// example: {"DocumentIncarnation": 1, "Events": [{"EventId": "..", "EventType": "Preempt", ..}]}
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+struct Document {
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Event {
+    pub event_id: String,
+    pub event_type: String, // Freeze, Reboot, Redeploy, Preempt or Terminate
+    #[serde(default)]
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub not_before: String, // example: Mon, 19 Sep 2016 18:29:47 GMT
+}
+
+// the image of the vm, either a custom one or from the marketplace
+// example: {"id": "", "offer": "0001-com-ubuntu-server-jammy", "publisher": "canonical", "sku": "22_04-lts-gen2", "version": "latest"}
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct ImageReference {
+    id: String,
+    publisher: String,
+    offer: String,
+    sku: String,
+    version: String,
+}
+
+impl ImageReference {
+    // the resource id of a custom image, or the urn of a marketplace one
+    fn name(self) -> Option<String> {
+        match (self.id.is_empty(), self.offer.is_empty()) {
+            (false, _) => Some(self.id),
+            (true, false) => Some(format!("{}:{}:{}:{}", self.publisher, self.offer, self.sku, self.version)),
+            (true, true) => None,
+        }
+    }
+}
+
+impl Event {
+    // the vm is going to be evicted or deleted
+    fn is_interrupt(&self) -> bool {
+        matches!(self.event_type.as_str(), "Preempt" | "Terminate")
+    }
+
+    // the minimum notice of the event, which is assumed if the NotBefore is empty or invalid.
+    // a spot vm is evicted at least 30 seconds later, and a deletion is at least 5 minutes
+    fn notice(&self) -> TimeDelta {
+        match self.event_type.as_str() {
+            "Preempt" => TimeDelta::seconds(30),
+            _ => TimeDelta::minutes(5),
+        }
+    }
+
+    // the vm is going to be unavailable for a while
+    fn is_maintenance(&self) -> bool {
+        matches!(self.event_type.as_str(), "Reboot" | "Redeploy")
+    }
+}
+
+// the spot vm of azure, monitored via scheduled events
+// reference: https://learn.microsoft.com/en-us/azure/virtual-machines/linux/scheduled-events
+pub struct Azure {
+    spot: Spot,
+    base_url: String,
+    acknowledge: bool,
+    // the events which concern this vm, updated by every query
+    events: Mutex<Vec<Event>>,
+    // the name of this vm, fetched by the first query
+    name: OnceLock<String>,
+}
+
+impl Azure {
+    pub fn new() -> Azure {
+        Azure::with_base_url(BASE_URL)
+    }
+
+    // the base url can be overridden, usually for testing
+    pub fn with_base_url(base_url: &str) -> Azure {
+        Azure {
+            spot: Spot::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            acknowledge: false,
+            events: Mutex::new(vec![]),
+            name: OnceLock::new(),
+        }
+    }
+
+    // whether to approve the interruption events after the local actions are done
+    pub fn with_acknowledge(mut self, acknowledge: bool) -> Azure {
+        self.acknowledge = acknowledge;
+        self
+    }
+
+    fn url(&self) -> String {
+        format!("{}/metadata/scheduledevents?api-version={API_VERSION}", self.base_url)
+    }
+
+    // GET /metadata/instance/{path}, a leaf of the instance metadata in plain text
+    fn instance(&self, path: &str) -> Result<Option<String>, Error> {
+        self.spot.text_with(
+            format!(
+                "{}/metadata/instance/{path}?api-version={INSTANCE_API_VERSION}&format=text",
+                self.base_url
+            ),
+            &[METADATA],
+        )
+    }
+
+    // GET /metadata/instance/compute/name, which is used in the resources of events.
+    // it never changes, so it is requested until it is known
+    fn name(&self) -> Result<Option<String>, Error> {
+        if let Some(n) = self.name.get() {
+            return Ok(Some(n.clone()));
+        }
+        let name = self.instance("compute/name")?;
+        if let Some(n) = &name {
+            let _ = self.name.set(n.clone());
+        }
+        Ok(name)
+    }
+
+    // GET /metadata/scheduledevents, only keep the events of this vm
+    pub fn events(&self) -> Result<Vec<Event>, Error> {
+        let text = self.spot.text_with(self.url(), &[METADATA])?;
+        Ok(self.parse(text.as_deref().unwrap_or("{}")))
+    }
+
+    // parse the document and remember the events of this vm
+    fn parse(&self, text: &str) -> Vec<Event> {
+        let doc: Document = serde_json::from_str(text).unwrap_or_else(|err| {
+            error!("[azure] invalid scheduled events {text}: {err}");
+            Document::default()
+        });
+        let name = self.name().unwrap_or_else(|err| {
+            error!("[azure] failed to get the name of vm: {err}");
+            None
+        });
+        let events: Vec<Event> = doc
+            .events
+            .into_iter()
+            .filter(|e| match &name {
+                Some(n) => e.resources.is_empty() || e.resources.contains(n),
+                None => true,
+            })
+            .collect();
+        *self.events.lock().unwrap() = events.clone();
+        events
+    }
+}
+
+impl Default for Azure {
+    fn default() -> Self {
+        Azure::new()
+    }
+}
+
+impl SpotProvider for Azure {
+    fn code(&self) -> Code {
+        Code::AzureInterrupt
+    }
+
+    // the termination time is the NotBefore of the interruption event,
+    // which may be empty if the event has been started, then the minimum notice is assumed
+    fn status(&self) -> Result<Status, Error> {
+        let res = self.spot.send(Method::GET, self.url(), &[METADATA])?;
+        if res.status() != StatusCode::OK {
+            let text = res.text()?;
+            error!("[azure] unknown error: {}", text);
+            return Ok(Status::Unknown(text));
+        }
+        match self.parse(&res.text()?).into_iter().find(Event::is_interrupt) {
+            Some(e) => Ok(Status::Terminating { at: parse_time(&e.not_before).unwrap_or_else(|| {
+                warn!("[azure] unknown NotBefore of the event {}: '{}'", e.event_id, e.not_before);
+                Utc::now() + e.notice()
+            }) }),
+            None => Ok(Status::Normal),
+        }
+    }
+
+    // GET /metadata/instance/compute/vmId
+    fn instance_id(&self) -> Result<String, Error> {
+        Ok(self.instance("compute/vmId")?.unwrap_or_default())
+    }
+
+    // GET /metadata/instance/compute/vmSize, example: Standard_D2s_v3
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        self.instance("compute/vmSize")
+    }
+
+    // GET /metadata/instance/compute/location, example: eastus
+    fn region(&self) -> Result<Option<String>, Error> {
+        self.instance("compute/location")
+    }
+
+    // GET /metadata/instance/compute/zone, empty if the vm isn't in an availability zone
+    fn zone(&self) -> Result<Option<String>, Error> {
+        self.instance("compute/zone")
+    }
+
+    // GET /metadata/instance/network/interface/0/ipv4/ipAddress/0/privateIpAddress
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        self.instance("network/interface/0/ipv4/ipAddress/0/privateIpAddress")
+    }
+
+    // GET /metadata/instance/network/interface/0/ipv4/ipAddress/0/publicIpAddress,
+    // empty if the vm has no public ip or it is of the standard sku
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        self.instance("network/interface/0/ipv4/ipAddress/0/publicIpAddress")
+    }
+
+    // GET /metadata/instance/compute/storageProfile/imageReference in json
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        let url = format!(
+            "{}/metadata/instance/compute/storageProfile/imageReference?api-version={INSTANCE_API_VERSION}",
+            self.base_url
+        );
+        Ok(self.spot.text_with(url, &[METADATA])?.and_then(|t| {
+            serde_json::from_str::<ImageReference>(&t)
+                .map_err(|err| error!("[azure] invalid image reference {t}: {err}"))
+                .ok()
+                .and_then(ImageReference::name)
+        }))
+    }
+
+    fn advisory(&self) -> Result<Option<Code>, Error> {
+        Ok(self
+            .events()?
+            .iter()
+            .any(Event::is_maintenance)
+            .then_some(Code::AzureMaintenance))
+    }
+
+    // POST /metadata/scheduledevents with all the known interruption events
+    // example: {"StartRequests": [{"EventId": "f020ba2e-3bc0-4c40-a10b-86575a9eabd5"}]}
+    fn acknowledge(&self) -> Result<(), Error> {
+        if !self.acknowledge {
+            return Ok(());
+        }
+        let requests: Vec<_> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e.is_interrupt())
+            .map(|e| json!({"EventId": e.event_id}))
+            .collect();
+        if requests.is_empty() {
+            return Ok(());
+        }
+        let body = json!({ "StartRequests": requests }).to_string();
+        self.spot
+            .send_body(Method::POST, self.url(), &[METADATA, ("Content-Type", "application/json")], body)?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use mockito::{Matcher, Server};
+
+    fn mock_events(server: &mut Server, body: &str) -> mockito::Mock {
+        server
+            .mock("GET", "/metadata/scheduledevents")
+            .match_query(Matcher::UrlEncoded("api-version".into(), API_VERSION.into()))
+            .match_header("Metadata", "true")
+            .with_body(body)
+            .create()
+    }
+
+    fn mock_name(server: &mut Server) -> mockito::Mock {
+        server
+            .mock("GET", "/metadata/instance/compute/name")
+            .match_query(Matcher::Any)
+            .with_body("spot-vm")
+            .create()
+    }
+
+    #[test]
+    fn test_preempt() {
+        let mut server = Server::new();
+        let name = mock_name(&mut server);
+        mock_events(&mut server, r#"{
+            "DocumentIncarnation": 2,
+            "Events": [{
+                "EventId": "f020ba2e-3bc0-4c40-a10b-86575a9eabd5",
+                "EventType": "Preempt",
+                "ResourceType": "VirtualMachine",
+                "Resources": ["spot-vm"],
+                "EventStatus": "Scheduled",
+                "NotBefore": "Mon, 19 Sep 2016 18:29:47 GMT"
+            }, {
+                "EventId": "9ab1b7b6-6d6c-4a73-8d2c-3c9e5f6a8d11",
+                "EventType": "Terminate",
+                "Resources": ["another-vm"],
+                "NotBefore": "Mon, 19 Sep 2016 18:39:47 GMT"
+            }]
+        }"#);
+        let ack = server
+            .mock("POST", "/metadata/scheduledevents")
+            .match_query(Matcher::Any)
+            .match_header("Metadata", "true")
+            .match_body(Matcher::Json(json!({
+                "StartRequests": [{"EventId": "f020ba2e-3bc0-4c40-a10b-86575a9eabd5"}]
+            })))
+            .create();
+
+        let azure = Azure::with_base_url(&server.url()).with_acknowledge(true);
+        let at = Utc.with_ymd_and_hms(2016, 9, 19, 18, 29, 47).unwrap();
+        assert_eq!(azure.status().unwrap(), Status::Terminating { at });
+        assert_eq!(azure.advisory().unwrap(), None);
+        azure.acknowledge().unwrap();
+        ack.assert();
+        // the name is requested once for all the queries
+        name.assert();
+    }
+
+    // the event has been started, so the NotBefore is empty
+    #[test]
+    fn test_started() {
+        let mut server = Server::new();
+        mock_name(&mut server);
+        mock_events(&mut server, r#"{
+            "DocumentIncarnation": 3,
+            "Events": [{
+                "EventId": "f020ba2e-3bc0-4c40-a10b-86575a9eabd5",
+                "EventType": "Preempt",
+                "Resources": ["spot-vm"],
+                "EventStatus": "Started",
+                "NotBefore": ""
+            }]
+        }"#);
+
+        let azure = Azure::with_base_url(&server.url());
+        match azure.status().unwrap() {
+            Status::Terminating { at } => assert!((Utc::now() + TimeDelta::seconds(30) - at).num_seconds() < 5),
+            s => panic!("unexpected status: {s:?}"),
+        }
+    }
+
+    #[test]
+    fn test_maintenance() {
+        let mut server = Server::new();
+        mock_name(&mut server);
+        mock_events(&mut server, r#"{
+            "DocumentIncarnation": 1,
+            "Events": [{
+                "EventId": "c8f6fd9d-8b4c-4b1b-9e1b-2b5c8c0e5b71",
+                "EventType": "Reboot",
+                "Resources": ["spot-vm"],
+                "NotBefore": "Mon, 19 Sep 2016 18:29:47 GMT"
+            }]
+        }"#);
+        // nothing to acknowledge, so it is never requested
+        let ack = server.mock("POST", Matcher::Any).expect(0).create();
+
+        let azure = Azure::with_base_url(&server.url()).with_acknowledge(true);
+        assert_eq!(azure.status().unwrap(), Status::Normal);
+        assert_eq!(azure.advisory().unwrap(), Some(Code::AzureMaintenance));
+        azure.acknowledge().unwrap();
+        ack.assert();
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut server = Server::new();
+        mock_name(&mut server);
+        mock_events(&mut server, r#"{"DocumentIncarnation": 0, "Events": []}"#);
+        server
+            .mock("GET", "/metadata/instance/compute/vmId")
+            .match_query(Matcher::Any)
+            .with_body("02aab8a4-74ef-476e-8182-f6d2ba4166a6")
+            .create();
+
+        let azure = Azure::with_base_url(&server.url());
+        assert_eq!(azure.status().unwrap(), Status::Normal);
+        assert_eq!(azure.instance_id().unwrap(), "02aab8a4-74ef-476e-8182-f6d2ba4166a6");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut server = Server::new();
+        for (path, body) in [
+            ("compute/vmSize", "Standard_D2s_v3"),
+            ("compute/location", "eastus"),
+            ("compute/zone", ""),
+            ("network/interface/0/ipv4/ipAddress/0/privateIpAddress", "10.0.0.4"),
+            ("network/interface/0/ipv4/ipAddress/0/publicIpAddress", "20.51.0.7"),
+        ] {
+            server
+                .mock("GET", format!("/metadata/instance/{path}").as_str())
+                .match_query(Matcher::AllOf(vec![
+                    Matcher::UrlEncoded("api-version".into(), INSTANCE_API_VERSION.into()),
+                    Matcher::UrlEncoded("format".into(), "text".into()),
+                ]))
+                .match_header("Metadata", "true")
+                .with_body(body)
+                .create();
+        }
+        server
+            .mock("GET", "/metadata/instance/compute/storageProfile/imageReference")
+            .match_query(Matcher::Any)
+            .match_header("Metadata", "true")
+            .with_body(r#"{
+                "id": "",
+                "offer": "0001-com-ubuntu-server-jammy",
+                "publisher": "canonical",
+                "sku": "22_04-lts-gen2",
+                "version": "latest"
+            }"#)
+            .create();
+
+        let azure = Azure::with_base_url(&server.url());
+        assert_eq!(azure.instance_type().unwrap(), Some("Standard_D2s_v3".to_string()));
+        assert_eq!(azure.region().unwrap(), Some("eastus".to_string()));
+        assert_eq!(azure.zone().unwrap(), Some("".to_string()));
+        assert_eq!(azure.private_ip().unwrap(), Some("10.0.0.4".to_string()));
+        assert_eq!(azure.public_ip().unwrap(), Some("20.51.0.7".to_string()));
+        assert_eq!(
+            azure.image_id().unwrap(),
+            Some("canonical:0001-com-ubuntu-server-jammy:22_04-lts-gen2:latest".to_string())
+        );
+    }
+
+    #[test]
+    fn test_image_reference() {
+        let custom = ImageReference { id: "/subscriptions/x/images/web".to_string(), ..Default::default() };
+        assert_eq!(custom.name(), Some("/subscriptions/x/images/web".to_string()));
+        assert_eq!(ImageReference::default().name(), None);
+    }
+}
@@ -0,0 +1,226 @@
+use super::{alicloud, aws, azure, gcp, huaweicloud, tencentcloud, volcengine, Spot};
+use crate::config::Provider;
+use log::{info, warn};
+use reqwest::{Method, StatusCode};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// the directory of DMI/SMBIOS information on linux
+pub const DMI_DIR: &str = "/sys/class/dmi/id";
+
+// each probe is limited to 500ms, all the probes run at the same time
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// a request which is only answered by the metadata service of a provider
+pub struct Probe {
+    pub provider: Provider,
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(&'static str, &'static str)>,
+}
+
+impl Probe {
+    fn new(provider: Provider, method: Method, url: String, headers: Vec<(&'static str, &'static str)>) -> Probe {
+        Probe {
+            provider,
+            method,
+            url,
+            headers,
+        }
+    }
+
+    // the metadata service answers with 200, gcp also answers with the header Metadata-Flavor
+    fn answer(&self, spot: &Spot) -> bool {
+        match spot.send_timeout(self.method.clone(), self.url.clone(), &self.headers, PROBE_TIMEOUT) {
+            Ok(res) => {
+                res.status() == StatusCode::OK
+                    && (self.provider != Provider::Gcp
+                    || res.headers().get("Metadata-Flavor").is_some_and(|v| v == "Google"))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+// Detector finds out the provider of current instance with DMI hints and metadata probes
+pub struct Detector {
+    dmi_dir: PathBuf,
+    probes: Vec<Probe>,
+}
+
+impl Detector {
+    pub fn new() -> Detector {
+        Detector::with(
+            Path::new(DMI_DIR),
+            vec![
+                Probe::new(Provider::AliCloud, Method::GET, format!("{}/latest/meta-data/instance-id", alicloud::BASE_URL), vec![]),
+                Probe::new(Provider::TencentCloud, Method::GET, format!("{}/latest/meta-data/instance-id", tencentcloud::BASE_URL), vec![]),
+                Probe::new(Provider::Volcengine, Method::GET, format!("{}/latest/meta-data/instance-id", volcengine::BASE_URL), vec![]),
+                // the following ones share 169.254.169.254, so the paths must be unique
+                Probe::new(Provider::HuaweiCloud, Method::GET, format!("{}/openstack/latest/meta_data.json", huaweicloud::BASE_URL), vec![]),
+                Probe::new(Provider::AwsEc2, Method::PUT, format!("{}/latest/api/token", aws::BASE_URL), vec![("X-aws-ec2-metadata-token-ttl-seconds", "60")]),
+                Probe::new(Provider::Azure, Method::GET, format!("{}/metadata/instance?api-version=2021-02-01", azure::BASE_URL), vec![("Metadata", "true")]),
+                Probe::new(Provider::Gcp, Method::GET, format!("{}/computeMetadata/v1/", gcp::BASE_URL), vec![("Metadata-Flavor", "Google")]),
+            ],
+        )
+    }
+
+    // the directory and probes can be overridden, usually for testing
+    pub fn with(dmi_dir: &Path, probes: Vec<Probe>) -> Detector {
+        Detector {
+            dmi_dir: dmi_dir.to_path_buf(),
+            probes,
+        }
+    }
+
+    // guess the provider from sys_vendor, product_name, bios_vendor and chassis_asset_tag
+    pub fn hint(&self) -> Option<Provider> {
+        let text = ["sys_vendor", "product_name", "bios_vendor", "chassis_asset_tag"]
+            .iter()
+            .filter_map(|f| fs::read_to_string(self.dmi_dir.join(f)).ok())
+            .collect::<Vec<String>>()
+            .join("\n")
+            .to_lowercase();
+        let hints = [
+            ("alibaba cloud", Provider::AliCloud),
+            ("tencent cloud", Provider::TencentCloud),
+            ("huawei", Provider::HuaweiCloud),
+            ("volcengine", Provider::Volcengine),
+            ("amazon ec2", Provider::AwsEc2),
+            ("google", Provider::Gcp),
+            // the chassis asset tag of all the azure vms
+            ("7783-7084-3265-9085-8269-3286-77", Provider::Azure),
+        ];
+        hints
+            .iter()
+            .find(|(k, _)| text.contains(k))
+            .map(|(_, p)| *p)
+    }
+
+    // the provider hinted by DMI is preferred if its metadata service answers,
+    // otherwise the first one answered in order. if nothing answers, trust the hint
+    // or fall back to LocalHost
+    pub fn detect(&self) -> Provider {
+        let hint = self.hint();
+        let answered: Vec<Provider> = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .probes
+                .iter()
+                .map(|p| s.spawn(move || (p.provider, p.answer(&Spot::new()))))
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|h| h.join().ok())
+                .filter(|(_, ok)| *ok)
+                .map(|(p, _)| p)
+                .collect()
+        });
+
+        let provider = match hint {
+            Some(h) if answered.contains(&h) => h,
+            _ => match answered.first() {
+                Some(p) => *p,
+                None => match hint {
+                    Some(h) => {
+                        warn!("spot - no metadata service answers, trust the DMI hint {:?}", h);
+                        h
+                    }
+                    None => Provider::LocalHost,
+                },
+            },
+        };
+        info!("spot - detected provider {:?} (DMI hint: {:?}, answered: {:?})", provider, hint, answered);
+        provider
+    }
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Detector::new()
+    }
+}
+
+// detect the provider of current instance with the default DMI directory and metadata services
+pub fn detect() -> Provider {
+    Detector::new().detect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn dmi(files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        for (name, content) in files {
+            let mut f = fs::File::create(dir.path().join(name)).unwrap();
+            writeln!(f, "{content}").unwrap();
+        }
+        dir
+    }
+
+    fn probes(url: &str) -> Vec<Probe> {
+        vec![
+            Probe::new(Provider::AliCloud, Method::GET, format!("{url}/ali"), vec![]),
+            Probe::new(Provider::AwsEc2, Method::PUT, format!("{url}/aws"), vec![]),
+            Probe::new(Provider::Gcp, Method::GET, format!("{url}/gcp"), vec![("Metadata-Flavor", "Google")]),
+        ]
+    }
+
+    #[test]
+    fn test_hint() {
+        let dir = dmi(&[("sys_vendor", "Alibaba Cloud"), ("product_name", "Alibaba Cloud ECS")]);
+        assert_eq!(Detector::with(dir.path(), vec![]).hint(), Some(Provider::AliCloud));
+        let dir = dmi(&[("chassis_asset_tag", "7783-7084-3265-9085-8269-3286-77")]);
+        assert_eq!(Detector::with(dir.path(), vec![]).hint(), Some(Provider::Azure));
+        let dir = dmi(&[("sys_vendor", "Dell Inc.")]);
+        assert_eq!(Detector::with(dir.path(), vec![]).hint(), None);
+        // no DMI at all, e.g. macOS
+        assert_eq!(Detector::with(Path::new("/not/exist"), vec![]).hint(), None);
+    }
+
+    #[test]
+    fn test_detect_by_probe() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/ali").with_status(404).create();
+        server.mock("PUT", "/aws").with_body("token").create();
+        // it is not gcp without the response header Metadata-Flavor
+        server.mock("GET", "/gcp").with_body("").create();
+
+        let dir = dmi(&[]);
+        let detector = Detector::with(dir.path(), probes(&server.url()));
+        assert_eq!(detector.detect(), Provider::AwsEc2);
+    }
+
+    #[test]
+    fn test_detect_prefer_hint() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/ali").with_body("i-001").create();
+        server.mock("PUT", "/aws").with_status(404).create();
+        server
+            .mock("GET", "/gcp")
+            .with_header("Metadata-Flavor", "Google")
+            .create();
+
+        let dir = dmi(&[("product_name", "Google Compute Engine")]);
+        let detector = Detector::with(dir.path(), probes(&server.url()));
+        assert_eq!(detector.detect(), Provider::Gcp);
+    }
+
+    #[test]
+    fn test_detect_nothing() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", mockito::Matcher::Any).with_status(404).create();
+
+        let dir = dmi(&[]);
+        let detector = Detector::with(dir.path(), probes(&server.url()));
+        assert_eq!(detector.detect(), Provider::LocalHost);
+        // trust the hint if the metadata service is blocked
+        let dir = dmi(&[("sys_vendor", "Amazon EC2")]);
+        let detector = Detector::with(dir.path(), probes(&server.url()));
+        assert_eq!(detector.detect(), Provider::AwsEc2);
+    }
+}
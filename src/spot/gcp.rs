@@ -0,0 +1,257 @@
+use super::{Spot, SpotProvider, Status};
+use crate::alert::Code;
+use chrono::{TimeDelta, Utc};
+use log::error;
+use reqwest::{Error, Method, StatusCode};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// the metadata server of gcp compute engine
+pub const BASE_URL: &str = "http://metadata.google.internal";
+
+// all the requests must carry this header
+const FLAVOR: (&str, &str) = ("Metadata-Flavor", "Google");
+
+// the longest time of a long-polling request, unit: second
+const WAIT_TIMEOUT: u64 = 30;
+
+// the preemptible or spot vm of gcp.
+// instead of polling every `interval` seconds, `status` waits for the change of `preempted`
+// with the etag of last response, so the preemption is detected as soon as it happens.
+// reference: https://cloud.google.com/compute/docs/instances/create-use-preemptible#detecting_if_an_instance_was_preempted
+// reference: https://cloud.google.com/compute/docs/metadata/querying-metadata#waitforchange
+pub struct Gcp {
+    spot: Spot,
+    base_url: String,
+    etag: Mutex<Option<String>>,
+}
+
+impl Gcp {
+    pub fn new() -> Gcp {
+        Gcp::with_base_url(BASE_URL)
+    }
+
+    // the base url can be overridden, usually for testing
+    pub fn with_base_url(base_url: &str) -> Gcp {
+        Gcp {
+            spot: Spot::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            etag: Mutex::new(None),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/computeMetadata/v1/instance/{path}", self.base_url)
+    }
+
+    fn text(&self, path: &str) -> Result<Option<String>, Error> {
+        self.spot.text_with(self.url(path), &[FLAVOR])
+    }
+
+    // the zone is a full path, example: projects/123456789012/zones/us-central1-a
+    fn zone_name(&self) -> Result<Option<String>, Error> {
+        Ok(self.text("zone")?.map(|z| last(&z)))
+    }
+}
+
+// the last part of a resource path
+fn last(path: &str) -> String {
+    path.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+impl Default for Gcp {
+    fn default() -> Self {
+        Gcp::new()
+    }
+}
+
+impl SpotProvider for Gcp {
+    fn code(&self) -> Code {
+        Code::GcpPreempted
+    }
+
+    // GET /computeMetadata/v1/instance/preempted, the result is TRUE or FALSE
+    // the first request returns at once, the following ones wait for the change
+    // gcp doesn't provide the termination time, the vm is stopped 30 seconds after preemption
+    fn status(&self) -> Result<Status, Error> {
+        let mut etag = self.etag.lock().unwrap();
+        let url = match etag.as_ref() {
+            Some(e) => format!(
+                "{}?wait_for_change=true&last_etag={e}&timeout_sec={WAIT_TIMEOUT}",
+                self.url("preempted")
+            ),
+            None => self.url("preempted"),
+        };
+        let res = self.spot.send_timeout(
+            Method::GET,
+            url,
+            &[FLAVOR],
+            Duration::from_secs(WAIT_TIMEOUT + 5),
+        )?;
+        if res.status() != StatusCode::OK {
+            let text = res.text()?;
+            error!("[gcp] unknown error: {}", text);
+            return Ok(Status::Unknown(text));
+        }
+        *etag = res
+            .headers()
+            .get("ETag")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        match res.text()?.trim() {
+            "TRUE" => Ok(Status::Terminating { at: Utc::now() + TimeDelta::seconds(30) }),
+            _ => Ok(Status::Normal),
+        }
+    }
+
+    // GET /computeMetadata/v1/instance/id
+    fn instance_id(&self) -> Result<String, Error> {
+        Ok(self.text("id")?.unwrap_or_default())
+    }
+
+    // GET /computeMetadata/v1/instance/machine-type
+    // example: projects/123456789012/machineTypes/e2-medium
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        Ok(self.text("machine-type")?.map(|t| last(&t)))
+    }
+
+    // the zone without the suffix, example: us-central1-a -> us-central1
+    fn region(&self) -> Result<Option<String>, Error> {
+        Ok(self.zone_name()?.and_then(|z| z.rsplit_once('-').map(|(r, _)| r.to_string())))
+    }
+
+    // GET /computeMetadata/v1/instance/zone
+    fn zone(&self) -> Result<Option<String>, Error> {
+        self.zone_name()
+    }
+
+    // GET /computeMetadata/v1/instance/network-interfaces/0/ip
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        self.text("network-interfaces/0/ip")
+    }
+
+    // GET /computeMetadata/v1/instance/network-interfaces/0/access-configs/0/external-ip,
+    // None if the vm has no external ip
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        self.text("network-interfaces/0/access-configs/0/external-ip")
+    }
+
+    // GET /computeMetadata/v1/instance/image
+    // example: projects/debian-cloud/global/images/debian-12-bookworm-v20240910
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        Ok(self.text("image")?.map(|i| last(&i)))
+    }
+
+    // GET /computeMetadata/v1/instance/maintenance-event
+    // NONE, MIGRATE_ON_HOST_MAINTENANCE or TERMINATE_ON_HOST_MAINTENANCE
+    fn advisory(&self) -> Result<Option<Code>, Error> {
+        let event = self.text("maintenance-event")?;
+        Ok(match event.as_deref() {
+            None | Some("NONE") => None,
+            Some(_) => Some(Code::GcpMaintenance),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    #[test]
+    fn test_wait_for_change() {
+        let mut server = Server::new();
+        let first = server
+            .mock("GET", "/computeMetadata/v1/instance/preempted")
+            .match_header("Metadata-Flavor", "Google")
+            .match_query(Matcher::Missing)
+            .with_header("ETag", "411261ca6c9e654e")
+            .with_body("FALSE")
+            .create();
+        let wait = server
+            .mock("GET", "/computeMetadata/v1/instance/preempted")
+            .match_header("Metadata-Flavor", "Google")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("wait_for_change".into(), "true".into()),
+                Matcher::UrlEncoded("last_etag".into(), "411261ca6c9e654e".into()),
+            ]))
+            .with_header("ETag", "9c8ae0a7b7a3d36f")
+            .with_body("TRUE")
+            .create();
+
+        let gcp = Gcp::with_base_url(&server.url());
+        assert_eq!(gcp.status().unwrap(), Status::Normal);
+        match gcp.status().unwrap() {
+            Status::Terminating { at } => assert!((at - Utc::now()).num_seconds() <= 30),
+            s => panic!("unexpected status: {s:?}"),
+        }
+        first.assert();
+        wait.assert();
+    }
+
+    #[test]
+    fn test_maintenance() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/computeMetadata/v1/instance/maintenance-event")
+            .match_header("Metadata-Flavor", "Google")
+            .with_body("TERMINATE_ON_HOST_MAINTENANCE")
+            .create();
+
+        let gcp = Gcp::with_base_url(&server.url());
+        assert_eq!(gcp.advisory().unwrap(), Some(Code::GcpMaintenance));
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/computeMetadata/v1/instance/maintenance-event")
+            .with_body("NONE")
+            .create();
+        server
+            .mock("GET", "/computeMetadata/v1/instance/id")
+            .with_body("4567890123456789012")
+            .create();
+        server
+            .mock("GET", "/computeMetadata/v1/instance/preempted")
+            .with_status(403)
+            .create();
+
+        let gcp = Gcp::with_base_url(&server.url());
+        assert_eq!(gcp.advisory().unwrap(), None);
+        assert_eq!(gcp.instance_id().unwrap(), "4567890123456789012");
+        // missing Metadata-Flavor or something else
+        assert!(matches!(gcp.status().unwrap(), Status::Unknown(_)));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut server = Server::new();
+        for (path, body) in [
+            ("machine-type", "projects/123456789012/machineTypes/e2-medium"),
+            ("zone", "projects/123456789012/zones/us-central1-a"),
+            ("network-interfaces/0/ip", "10.128.0.2"),
+            ("image", "projects/debian-cloud/global/images/debian-12-bookworm-v20240910"),
+        ] {
+            server
+                .mock("GET", format!("/computeMetadata/v1/instance/{path}").as_str())
+                .match_header("Metadata-Flavor", "Google")
+                .with_body(body)
+                .create();
+        }
+        server
+            .mock("GET", "/computeMetadata/v1/instance/network-interfaces/0/access-configs/0/external-ip")
+            .with_status(404)
+            .create();
+
+        let gcp = Gcp::with_base_url(&server.url());
+        assert_eq!(gcp.instance_type().unwrap(), Some("e2-medium".to_string()));
+        assert_eq!(gcp.region().unwrap(), Some("us-central1".to_string()));
+        assert_eq!(gcp.zone().unwrap(), Some("us-central1-a".to_string()));
+        assert_eq!(gcp.private_ip().unwrap(), Some("10.128.0.2".to_string()));
+        assert_eq!(gcp.public_ip().unwrap(), None);
+        assert_eq!(gcp.image_id().unwrap(), Some("debian-12-bookworm-v20240910".to_string()));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::config::{Action, Step};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use reqwest::blocking::Client;
+use reqwest::Method;
+use std::fmt;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// the time reserved for sending the report before the instance is released
+const RESERVE: Duration = Duration::from_secs(5);
+
+// only keep the tail of logs, the alert should be short
+const LOG_LIMIT: usize = 300;
+
+// how long the outputs are waited for after the command exits,
+// a process left in the background may hold the pipes
+const LINGER: Duration = Duration::from_secs(1);
+
+// the result of a step
+#[derive(Debug)]
+pub struct Outcome {
+    pub name: String,
+    pub budget: Duration,
+    pub elapsed: Duration,
+    pub result: Result<(), String>,
+    pub log: String,
+}
+
+impl fmt::Display for Outcome {
+    // example: [成功] docker stop web（用时 2.1s / 预算 20s）
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match &self.result {
+            Ok(_) => "成功".to_string(),
+            Err(e) => format!("失败: {e}"),
+        };
+        write!(
+            f,
+            "[{status}] {}（用时 {:.1}s / 预算 {}s）",
+            self.name,
+            self.elapsed.as_secs_f64(),
+            self.budget.as_secs()
+        )?;
+        if !self.log.is_empty() {
+            write!(f, "\n{}", self.log)?;
+        }
+        Ok(())
+    }
+}
+
+// Pipeline runs the `[[spot.on_interrupt]]` steps in order within the termination window
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<Step>) -> Pipeline {
+        Pipeline { steps }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // every step is limited by its own timeout and the time left before the deadline.
+    // a failed step doesn't stop the following ones, the steps without time are skipped
+    pub fn run(&self, deadline: DateTime<Utc>) -> Vec<Outcome> {
+        let mut outcomes = vec![];
+        for step in self.steps.iter() {
+            let left = (deadline - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+                .saturating_sub(RESERVE);
+            let budget = match step.timeout {
+                Some(t) => left.min(Duration::from_secs(t)),
+                None => left,
+            };
+            let name = describe(&step.action);
+            if budget.is_zero() {
+                outcomes.push(Outcome {
+                    name,
+                    budget,
+                    elapsed: Duration::ZERO,
+                    result: Err("no time left".to_string()),
+                    log: "".to_string(),
+                });
+                continue;
+            }
+            info!("spot - run hook {name} within {}s", budget.as_secs());
+            let start = Instant::now();
+            let (result, log) = run(&step.action, budget);
+            if let Err(e) = &result {
+                error!("spot - hook {name} failed: {e}");
+            }
+            outcomes.push(Outcome {
+                name,
+                budget,
+                elapsed: start.elapsed(),
+                result,
+                log: tail(&log),
+            });
+        }
+        outcomes
+    }
+}
+
+// a readable name of the action
+fn describe(action: &Action) -> String {
+    match action {
+        Action::Command { command, args } => format!("{command} {}", args.join(" ")).trim().to_string(),
+        Action::Systemd { units } => format!("systemctl stop {}", units.join(" ")),
+        Action::Docker { containers } => format!("docker stop {}", containers.join(" ")),
+        Action::Sync { source, target } => format!("rsync {source} {target}"),
+        Action::Http { url, method, .. } => format!("{method} {url}"),
+    }
+}
+
+fn run(action: &Action, budget: Duration) -> (Result<(), String>, String) {
+    match action {
+        Action::Command { command, args } => execute(command, args, budget),
+        Action::Systemd { units } => {
+            let mut args = vec!["stop".to_string()];
+            args.extend(units.iter().cloned());
+            execute("systemctl", &args, budget)
+        }
+        Action::Docker { containers } => {
+            // leave a second for docker to kill the containers after the grace period
+            let grace = budget.as_secs().saturating_sub(1).to_string();
+            let mut args = vec!["stop".to_string(), "--time".to_string(), grace];
+            args.extend(containers.iter().cloned());
+            execute("docker", &args, budget)
+        }
+        Action::Sync { source, target } => {
+            execute("rsync", &["-a".to_string(), source.clone(), target.clone()], budget)
+        }
+        Action::Http { url, method, body } => request(url, method, body, budget),
+    }
+}
+
+// run a command and kill it if it is out of budget, the logs include stdout and stderr.
+// the command leads its own process group, so that its children are killed with it
+fn execute(program: &str, args: &[String], budget: Duration) -> (Result<(), String>, String) {
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(e) => return (Err(e.to_string()), "".to_string()),
+    };
+    // read outputs in child threads, otherwise the command may be blocked by a full pipe
+    let stdout = child.stdout.take().map(read);
+    let stderr = child.stderr.take().map(read);
+
+    let start = Instant::now();
+    let result = loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break Ok(()),
+            Ok(Some(status)) => break Err(status.to_string()),
+            Ok(None) if start.elapsed() >= budget => {
+                kill(&mut child);
+                break Err("timeout".to_string());
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(e) => break Err(e.to_string()),
+        }
+    };
+    // the readers which are still blocked are left behind
+    let linger = Instant::now() + LINGER;
+    let log = [stdout, stderr]
+        .into_iter()
+        .flatten()
+        .filter_map(|rx| rx.recv_timeout(linger.saturating_duration_since(Instant::now())).ok())
+        .collect::<Vec<String>>()
+        .join("")
+        .trim()
+        .to_string();
+    (result, log)
+}
+
+// kill the whole process group, whose id is the pid of the leader
+fn kill(child: &mut Child) {
+    if let Ok(pid) = i32::try_from(child.id()) {
+        unsafe { libc::kill(-pid, libc::SIGKILL) };
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn read<R: Read + Send + 'static>(mut r: R) -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut s = String::new();
+        let _ = r.read_to_string(&mut s);
+        let _ = tx.send(s);
+    });
+    rx
+}
+
+fn request(url: &str, method: &str, body: &str, budget: Duration) -> (Result<(), String>, String) {
+    let method = match Method::from_bytes(method.to_uppercase().as_bytes()) {
+        Ok(m) => m,
+        Err(e) => return (Err(e.to_string()), "".to_string()),
+    };
+    let res = Client::new()
+        .request(method, url)
+        .timeout(budget)
+        .body(body.to_string())
+        .send();
+    match res {
+        Ok(r) => {
+            let status = r.status();
+            let text = r.text().unwrap_or_default();
+            match status.is_success() {
+                true => (Ok(()), text),
+                false => (Err(status.to_string()), text),
+            }
+        }
+        Err(e) => (Err(e.to_string()), "".to_string()),
+    }
+}
+
+fn tail(log: &str) -> String {
+    let chars: Vec<char> = log.chars().collect();
+    match chars.len() > LOG_LIMIT {
+        true => format!("...{}", chars[chars.len() - LOG_LIMIT..].iter().collect::<String>()),
+        false => log.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn command(command: &str, args: &[&str], timeout: Option<u64>) -> Step {
+        Step {
+            action: Action::Command {
+                command: command.to_string(),
+                args: args.iter().map(|a| a.to_string()).collect(),
+            },
+            timeout,
+        }
+    }
+
+    #[test]
+    fn test_run_in_order() {
+        let pipeline = Pipeline::new(vec![
+            command("echo", &["hello"], None),
+            command("false", &[], Some(10)),
+            command("/not/exist", &[], None),
+        ]);
+        let outcomes = pipeline.run(Utc::now() + TimeDelta::seconds(60));
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[0].name, "echo hello");
+        assert_eq!(outcomes[0].result, Ok(()));
+        assert_eq!(outcomes[0].log, "hello");
+        // limited by the window, 5 seconds are reserved
+        assert!(outcomes[0].budget <= Duration::from_secs(55));
+        assert_eq!(outcomes[1].budget, Duration::from_secs(10));
+        assert!(outcomes[1].result.is_err());
+        assert!(outcomes[2].result.is_err());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let pipeline = Pipeline::new(vec![command("sleep", &["5"], Some(1))]);
+        let outcomes = pipeline.run(Utc::now() + TimeDelta::seconds(60));
+        assert_eq!(outcomes[0].result, Err("timeout".to_string()));
+        assert!(outcomes[0].elapsed < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_timeout_with_children() {
+        // the shell forks the sleep, which holds the pipes
+        let pipeline = Pipeline::new(vec![command("sh", &["-c", "echo started; sleep 600; echo done"], Some(1))]);
+        let outcomes = pipeline.run(Utc::now() + TimeDelta::seconds(60));
+        assert_eq!(outcomes[0].result, Err("timeout".to_string()));
+        assert_eq!(outcomes[0].log, "started");
+        assert!(outcomes[0].elapsed < Duration::from_secs(3));
+
+        // a process left in the background doesn't block the pipeline
+        let start = Instant::now();
+        let pipeline = Pipeline::new(vec![command("sh", &["-c", "echo started; sleep 30 &"], Some(5))]);
+        let outcomes = pipeline.run(Utc::now() + TimeDelta::seconds(60));
+        assert_eq!(outcomes[0].result, Ok(()));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_no_time_left() {
+        let pipeline = Pipeline::new(vec![command("echo", &["hello"], None)]);
+        let outcomes = pipeline.run(Utc::now() + TimeDelta::seconds(3));
+        assert_eq!(outcomes[0].result, Err("no time left".to_string()));
+        assert_eq!(outcomes[0].to_string(), "[失败: no time left] echo hello（用时 0.0s / 预算 0s）");
+    }
+
+    #[test]
+    fn test_http() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("PUT", "/drain")
+            .match_body("now")
+            .with_body("drained")
+            .create();
+        let pipeline = Pipeline::new(vec![Step {
+            action: Action::Http {
+                url: format!("{}/drain", server.url()),
+                method: "put".to_string(),
+                body: "now".to_string(),
+            },
+            timeout: Some(5),
+        }]);
+        let outcomes = pipeline.run(Utc::now() + TimeDelta::seconds(60));
+        assert_eq!(outcomes[0].result, Ok(()));
+        assert_eq!(outcomes[0].log, "drained");
+        mock.assert();
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(&Action::Systemd { units: vec!["a".into(), "b".into()] }), "systemctl stop a b");
+        assert_eq!(describe(&Action::Docker { containers: vec!["web".into()] }), "docker stop web");
+        assert_eq!(describe(&Action::Sync { source: "/data".into(), target: "/backup".into() }), "rsync /data /backup");
+    }
+
+    #[test]
+    fn test_tail() {
+        let log = "x".repeat(LOG_LIMIT + 10);
+        assert_eq!(tail(&log).len(), LOG_LIMIT + 3);
+        assert_eq!(tail("ok"), "ok");
+    }
+}
@@ -0,0 +1,221 @@
+use super::{parse_time, Spot, SpotProvider, Status};
+use crate::alert::Code;
+use chrono::TimeDelta;
+use log::error;
+use reqwest::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// the metadata service of huawei cloud ecs
+pub const BASE_URL: &str = "http://169.254.169.254";
+
+// the notice before the release, assumed if the time of the notice can't be parsed
+const NOTICE: TimeDelta = TimeDelta::minutes(2);
+
+// the interruption notice of a spot instance
+// example: {"action": "terminate", "time": "2024-09-18T08:22:00Z"}
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct InstanceAction {
+    pub action: String,
+    #[serde(alias = "timestamp")]
+    pub time: String,
+}
+
+// the openstack metadata, only the fields we need
+// example: {"region_id": "cn-north-4", "meta": {"metering.image_id": "..", ..}, ..}
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct OpenStack {
+    region_id: Option<String>,
+    meta: HashMap<String, String>,
+}
+
+// the spot instance of huawei cloud (alias ecs)
+// reference: https://support.huaweicloud.com/usermanual-ecs/ecs_03_0505.html
+pub struct HuaweiCloud {
+    spot: Spot,
+    base_url: String,
+}
+
+impl HuaweiCloud {
+    pub fn new() -> HuaweiCloud {
+        HuaweiCloud::with_base_url(BASE_URL)
+    }
+
+    // the base url can be overridden, usually for testing
+    pub fn with_base_url(base_url: &str) -> HuaweiCloud {
+        HuaweiCloud {
+            spot: Spot::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/latest/meta-data/{path}", self.base_url)
+    }
+
+    // GET /openstack/latest/meta_data.json, which has the region and the image
+    fn openstack(&self) -> Result<OpenStack, Error> {
+        let text = self.spot.text(format!("{}/openstack/latest/meta_data.json", self.base_url))?;
+        Ok(text
+            .and_then(|t| {
+                serde_json::from_str(&t)
+                    .map_err(|err| error!("[huaweicloud] invalid metadata {t}: {err}"))
+                    .ok()
+            })
+            .unwrap_or_default())
+    }
+
+    // GET /latest/meta-data/spot/instance-action
+    // None if the instance is not going to be released, or the notice can't be parsed
+    pub fn action(&self) -> Result<Option<InstanceAction>, Error> {
+        Ok(self.spot.text(self.url("spot/instance-action"))?.and_then(|t| {
+            serde_json::from_str(&t)
+                .map_err(|err| error!("[huaweicloud] invalid instance action {t}: {err}"))
+                .ok()
+        }))
+    }
+}
+
+impl Default for HuaweiCloud {
+    fn default() -> Self {
+        HuaweiCloud::new()
+    }
+}
+
+impl SpotProvider for HuaweiCloud {
+    fn code(&self) -> Code {
+        Code::HuaweiCloudInterrupt
+    }
+
+    // GET /latest/meta-data/spot/instance-action, the time is taken from the notice
+    fn status(&self) -> Result<Status, Error> {
+        self.spot.query_by(self.url("spot/instance-action"), &[], NOTICE, |body| {
+            serde_json::from_str::<InstanceAction>(body)
+                .ok()
+                .and_then(|a| parse_time(&a.time))
+        })
+    }
+
+    // GET /latest/meta-data/instance-id
+    fn instance_id(&self) -> Result<String, Error> {
+        Ok(self.spot.text(self.url("instance-id"))?.unwrap_or_default())
+    }
+
+    // GET /latest/meta-data/instance-type
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("instance-type"))
+    }
+
+    // the region_id of the openstack metadata
+    fn region(&self) -> Result<Option<String>, Error> {
+        Ok(self.openstack()?.region_id)
+    }
+
+    // GET /latest/meta-data/placement/availability-zone
+    fn zone(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("placement/availability-zone"))
+    }
+
+    // GET /latest/meta-data/local-ipv4
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("local-ipv4"))
+    }
+
+    // GET /latest/meta-data/public-ipv4, the elastic ip if any
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("public-ipv4"))
+    }
+
+    // the metering.image_id of the openstack metadata
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        Ok(self.openstack()?.meta.remove("metering.image_id"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_status() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/latest/meta-data/spot/instance-action")
+            .with_body(r#"{"action": "terminate", "timestamp": "2024-09-18T08:22:00Z"}"#)
+            .expect(2)
+            .create();
+
+        let ecs = HuaweiCloud::with_base_url(&server.url());
+        let at = Utc.with_ymd_and_hms(2024, 9, 18, 8, 22, 0).unwrap();
+        assert_eq!(ecs.status().unwrap(), Status::Terminating { at });
+        assert_eq!(ecs.action().unwrap(), Some(InstanceAction {
+            action: "terminate".to_string(),
+            time: "2024-09-18T08:22:00Z".to_string(),
+        }));
+        mock.assert();
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/latest/meta-data/spot/instance-action")
+            .with_status(404)
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/instance-id")
+            .with_body("2a3ba5c4-5b8e-4b0b-9a1e-0b6c1f0d2e3f")
+            .create();
+
+        let ecs = HuaweiCloud::with_base_url(&server.url());
+        assert_eq!(ecs.status().unwrap(), Status::Normal);
+        assert_eq!(ecs.instance_id().unwrap(), "2a3ba5c4-5b8e-4b0b-9a1e-0b6c1f0d2e3f");
+    }
+
+    #[test]
+    fn test_invalid_action() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/latest/meta-data/spot/instance-action")
+            .with_body("terminate")
+            .create();
+
+        let ecs = HuaweiCloud::with_base_url(&server.url());
+        // still be released, but the time is unknown
+        assert!(matches!(ecs.status().unwrap(), Status::Terminating { .. }));
+        assert_eq!(ecs.action().unwrap(), None);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut server = mockito::Server::new();
+        for (path, body) in [
+            ("instance-type", "s6.large.2"),
+            ("placement/availability-zone", "cn-north-4a"),
+            ("local-ipv4", "192.168.0.12"),
+        ] {
+            server.mock("GET", format!("/latest/meta-data/{path}").as_str()).with_body(body).create();
+        }
+        server.mock("GET", "/latest/meta-data/public-ipv4").with_status(404).create();
+        server
+            .mock("GET", "/openstack/latest/meta_data.json")
+            .with_body(r#"{
+                "uuid": "2a3ba5c4-5b8e-4b0b-9a1e-0b6c1f0d2e3f",
+                "availability_zone": "cn-north-4a",
+                "region_id": "cn-north-4",
+                "meta": {"metering.image_id": "3d2bd5d4-6c2b-4b2e-9e3c-6f5c4b9a1e7d", "vpc_id": "x"},
+                "project_id": "0bc9c2a1"
+            }"#)
+            .create();
+
+        let ecs = HuaweiCloud::with_base_url(&server.url());
+        assert_eq!(ecs.instance_type().unwrap(), Some("s6.large.2".to_string()));
+        assert_eq!(ecs.region().unwrap(), Some("cn-north-4".to_string()));
+        assert_eq!(ecs.zone().unwrap(), Some("cn-north-4a".to_string()));
+        assert_eq!(ecs.private_ip().unwrap(), Some("192.168.0.12".to_string()));
+        assert_eq!(ecs.public_ip().unwrap(), None);
+        assert_eq!(ecs.image_id().unwrap(), Some("3d2bd5d4-6c2b-4b2e-9e3c-6f5c4b9a1e7d".to_string()));
+    }
+}
@@ -0,0 +1,162 @@
+use super::{Spot, SpotProvider, Status};
+use crate::alert::Code;
+use chrono::TimeDelta;
+use reqwest::Error;
+
+// the metadata service of tencentcloud cvm
+pub const BASE_URL: &str = "http://metadata.tencentyun.com";
+
+// the notice before the release, assumed if the termination time can't be parsed
+const NOTICE: TimeDelta = TimeDelta::minutes(2);
+
+// the spot instance of tencentcloud (alias cvm)
+// reference: https://cloud.tencent.com/document/product/213/37970
+pub struct TencentCloud {
+    spot: Spot,
+    base_url: String,
+}
+
+impl TencentCloud {
+    pub fn new() -> TencentCloud {
+        TencentCloud::with_base_url(BASE_URL)
+    }
+
+    // the base url can be overridden, usually for testing
+    pub fn with_base_url(base_url: &str) -> TencentCloud {
+        TencentCloud {
+            spot: Spot::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/latest/meta-data/{path}", self.base_url)
+    }
+}
+
+impl Default for TencentCloud {
+    fn default() -> Self {
+        TencentCloud::new()
+    }
+}
+
+impl SpotProvider for TencentCloud {
+    fn code(&self) -> Code {
+        Code::TencentCloudInterrupt
+    }
+
+    // GET /latest/meta-data/spot/termination-time
+    // example: 2018-11-13T06:05:45Z
+    fn status(&self) -> Result<Status, Error> {
+        self.spot.query(self.url("spot/termination-time"), NOTICE)
+    }
+
+    // GET /latest/meta-data/instance-id
+    fn instance_id(&self) -> Result<String, Error> {
+        Ok(self.spot.text(self.url("instance-id"))?.unwrap_or_default())
+    }
+
+    // GET /latest/meta-data/instance/instance-type
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("instance/instance-type"))
+    }
+
+    // GET /latest/meta-data/placement/region
+    fn region(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("placement/region"))
+    }
+
+    // GET /latest/meta-data/placement/zone
+    fn zone(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("placement/zone"))
+    }
+
+    // GET /latest/meta-data/local-ipv4
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("local-ipv4"))
+    }
+
+    // GET /latest/meta-data/public-ipv4
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("public-ipv4"))
+    }
+
+    // GET /latest/meta-data/instance/image-id
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("instance/image-id"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_status() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/latest/meta-data/spot/termination-time")
+            .with_body("2018-11-13T06:05:45Z")
+            .create();
+
+        let cvm = TencentCloud::with_base_url(&server.url());
+        let at = Utc.with_ymd_and_hms(2018, 11, 13, 6, 5, 45).unwrap();
+        assert_eq!(cvm.status().unwrap(), Status::Terminating { at });
+        mock.assert();
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/latest/meta-data/spot/termination-time")
+            .with_status(404)
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/instance-id")
+            .with_body("ins-r8hr2upy")
+            .create();
+
+        let cvm = TencentCloud::with_base_url(&server.url());
+        assert_eq!(cvm.status().unwrap(), Status::Normal);
+        assert_eq!(cvm.instance_id().unwrap(), "ins-r8hr2upy");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/latest/meta-data/instance/instance-type")
+            .with_body("S5.MEDIUM2")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/placement/region")
+            .with_body("ap-guangzhou")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/placement/zone")
+            .with_body("ap-guangzhou-3")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/local-ipv4")
+            .with_body("10.0.0.8")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/public-ipv4")
+            .with_body("129.204.0.1")
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/instance/image-id")
+            .with_body("img-9qabwvbn")
+            .create();
+
+        let cvm = TencentCloud::with_base_url(&server.url());
+        assert_eq!(cvm.instance_type().unwrap(), Some("S5.MEDIUM2".to_string()));
+        assert_eq!(cvm.region().unwrap(), Some("ap-guangzhou".to_string()));
+        assert_eq!(cvm.zone().unwrap(), Some("ap-guangzhou-3".to_string()));
+        assert_eq!(cvm.private_ip().unwrap(), Some("10.0.0.8".to_string()));
+        assert_eq!(cvm.public_ip().unwrap(), Some("129.204.0.1".to_string()));
+        assert_eq!(cvm.image_id().unwrap(), Some("img-9qabwvbn".to_string()));
+    }
+}
@@ -0,0 +1,149 @@
+use super::{Spot, SpotProvider, Status};
+use crate::alert::Code;
+use chrono::TimeDelta;
+use reqwest::Error;
+
+// the metadata service of volcengine ecs
+pub const BASE_URL: &str = "http://100.96.0.96";
+
+// the notice before the release, assumed if the termination time can't be parsed
+const NOTICE: TimeDelta = TimeDelta::minutes(2);
+
+// the preemptible instance of volcengine (alias ecs)
+// the termination time is returned in plain text, 404 means that it won't be released
+// reference: https://www.volcengine.com/docs/6396/76567
+pub struct Volcengine {
+    spot: Spot,
+    base_url: String,
+}
+
+impl Volcengine {
+    pub fn new() -> Volcengine {
+        Volcengine::with_base_url(BASE_URL)
+    }
+
+    // the base url can be overridden, usually for testing
+    pub fn with_base_url(base_url: &str) -> Volcengine {
+        Volcengine {
+            spot: Spot::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/latest/meta-data/{path}", self.base_url)
+    }
+}
+
+impl Default for Volcengine {
+    fn default() -> Self {
+        Volcengine::new()
+    }
+}
+
+impl SpotProvider for Volcengine {
+    fn code(&self) -> Code {
+        Code::VolcengineInterrupt
+    }
+
+    // GET /latest/meta-data/spot/termination-time
+    // example: 2024-09-18T08:22:00Z
+    fn status(&self) -> Result<Status, Error> {
+        self.spot.query(self.url("spot/termination-time"), NOTICE)
+    }
+
+    // GET /latest/meta-data/instance-id
+    fn instance_id(&self) -> Result<String, Error> {
+        Ok(self.spot.text(self.url("instance-id"))?.unwrap_or_default())
+    }
+
+    // GET /latest/meta-data/instance-type
+    fn instance_type(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("instance-type"))
+    }
+
+    // GET /latest/meta-data/region-id
+    fn region(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("region-id"))
+    }
+
+    // GET /latest/meta-data/zone-id
+    fn zone(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("zone-id"))
+    }
+
+    // GET /latest/meta-data/private-ipv4
+    fn private_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("private-ipv4"))
+    }
+
+    // GET /latest/meta-data/public-ipv4, the elastic ip if any
+    fn public_ip(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("public-ipv4"))
+    }
+
+    // GET /latest/meta-data/image-id
+    fn image_id(&self) -> Result<Option<String>, Error> {
+        self.spot.text(self.url("image-id"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_status() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/latest/meta-data/spot/termination-time")
+            .with_body("2024-09-18T08:22:00Z")
+            .create();
+
+        let ecs = Volcengine::with_base_url(&server.url());
+        let at = Utc.with_ymd_and_hms(2024, 9, 18, 8, 22, 0).unwrap();
+        assert_eq!(ecs.status().unwrap(), Status::Terminating { at });
+        mock.assert();
+    }
+
+    #[test]
+    fn test_normal() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/latest/meta-data/spot/termination-time")
+            .with_status(404)
+            .create();
+        server
+            .mock("GET", "/latest/meta-data/instance-id")
+            .with_body("i-ybzixa3nh0l5k8xmu7fz")
+            .create();
+
+        let ecs = Volcengine::with_base_url(&server.url());
+        assert_eq!(ecs.status().unwrap(), Status::Normal);
+        assert_eq!(ecs.instance_id().unwrap(), "i-ybzixa3nh0l5k8xmu7fz");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut server = mockito::Server::new();
+        for (path, body) in [
+            ("instance-type", "ecs.g3i.large"),
+            ("region-id", "cn-beijing"),
+            ("zone-id", "cn-beijing-a"),
+            ("private-ipv4", "172.16.0.8"),
+            ("public-ipv4", "101.126.0.9"),
+            ("image-id", "image-ybqi99s7yq8rx7mnk44b"),
+        ] {
+            server.mock("GET", format!("/latest/meta-data/{path}").as_str()).with_body(body).create();
+        }
+
+        let ecs = Volcengine::with_base_url(&server.url());
+        assert_eq!(ecs.instance_type().unwrap(), Some("ecs.g3i.large".to_string()));
+        assert_eq!(ecs.region().unwrap(), Some("cn-beijing".to_string()));
+        assert_eq!(ecs.zone().unwrap(), Some("cn-beijing-a".to_string()));
+        assert_eq!(ecs.private_ip().unwrap(), Some("172.16.0.8".to_string()));
+        assert_eq!(ecs.public_ip().unwrap(), Some("101.126.0.9".to_string()));
+        assert_eq!(ecs.image_id().unwrap(), Some("image-ybqi99s7yq8rx7mnk44b".to_string()));
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt::Write;
+
+// a plain text table aligned to the left, the first row is the header.
+// the columns are separated by two spaces, example:
+// RANK  TYPE
+// 1     ecs.g7.large
+pub fn render(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or_default();
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| rows.iter().filter_map(|r| r.get(i)).map(|c| c.chars().count()).max().unwrap_or_default())
+        .collect();
+    let mut table = String::new();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect::<Vec<String>>()
+            .join("  ");
+        let _ = writeln!(table, "{}", line.trim_end());
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let rows = vec![
+            vec!["ID".to_string(), "TYPE".to_string(), "ZONE".to_string()],
+            vec!["i-1".to_string(), "ecs.g7.large".to_string(), "".to_string()],
+        ];
+        assert_eq!(render(&rows), "ID   TYPE          ZONE\ni-1  ecs.g7.large\n");
+        assert_eq!(render(&[]), "");
+    }
+}